@@ -0,0 +1,185 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::{OsStr, OsString},
+    path::PathBuf,
+};
+
+use crate::{args::HookArgs, facts::host_facts};
+
+/// Separates the base name of an alternate file from its conditions, as in
+/// `.gitconfig##hostname.work` or `.bashrc##os.Linux,class.laptop`.
+const ALTERNATE_SEPARATOR: &str = "##";
+
+/// What alternate files are matched against: the host facts and the classes given with --class.
+/// Gathering the facts reads files and searches `PATH`, so it's built once per run.
+#[derive(Debug)]
+pub struct AlternateContext {
+    facts: HashMap<String, String>,
+    classes: Vec<String>,
+}
+
+impl AlternateContext {
+    pub fn new(args: &HookArgs) -> Self {
+        let facts = if args.facts.is_empty() {
+            host_facts()
+        } else {
+            args.facts.clone()
+        };
+
+        Self {
+            facts: facts.into_iter().collect(),
+            classes: args.class.clone(),
+        }
+    }
+
+    /// The host facts, also used by the conditions of manifest entries and templates.
+    pub fn facts(&self) -> &HashMap<String, String> {
+        &self.facts
+    }
+
+    /// Scores how specifically the conditions of an alternate match this machine, or `None`
+    /// if any of them doesn't. Later conditions in this list are more specific and weigh more.
+    fn score(&self, conditions: &str) -> Option<u32> {
+        let mut score = 0;
+
+        for condition in conditions.split(',') {
+            let (key, value) = condition.split_once('.').unwrap_or((condition, ""));
+
+            let (matches, weight) = match key {
+                "default" => (true, 0),
+                "os" | "o" => (self.fact_matches("os", value), 1),
+                "distro" | "d" => (self.fact_matches("distro", value), 2),
+                "arch" | "a" => (self.fact_matches("arch", value), 4),
+                "class" | "c" => (self.classes.iter().any(|class| class == value), 8),
+                "hostname" | "h" => (self.fact_matches("hostname", value), 16),
+                "user" | "u" => (self.fact_matches("user", value), 32),
+                _ => (false, 0),
+            };
+
+            if !matches {
+                return None;
+            }
+
+            score += weight;
+        }
+
+        Some(score)
+    }
+
+    fn fact_matches(&self, name: &str, value: &str) -> bool {
+        // `uname -s` names macOS Darwin, which is what alternates written for yadm use.
+        let value = match value {
+            value if name == "os" && value.eq_ignore_ascii_case("darwin") => "macos",
+            value => value,
+        };
+
+        self.facts
+            .get(name)
+            .is_some_and(|fact| fact.eq_ignore_ascii_case(value))
+    }
+}
+
+/// The base name of an alternate file name, if it is one.
+pub fn alternate_base(name: &OsStr) -> Option<&str> {
+    name.to_str()?
+        .split_once(ALTERNATE_SEPARATOR)
+        .map(|(base, _)| base)
+        .filter(|base| !base.is_empty())
+}
+
+/// Picks the best matching name for every base name among the names in a directory. A plain name
+/// is used when no alternate for it matches, and alternates which don't match are left out.
+pub fn select_alternates(
+    names: impl IntoIterator<Item = OsString>,
+    context: &AlternateContext,
+) -> BTreeMap<OsString, OsString> {
+    let mut selected = BTreeMap::<OsString, (Option<u32>, OsString)>::new();
+
+    for name in names {
+        let (base, score) = match name
+            .to_str()
+            .and_then(|name| name.split_once(ALTERNATE_SEPARATOR))
+        {
+            Some((base, conditions)) if !base.is_empty() => match context.score(conditions) {
+                Some(score) => (OsString::from(base), Some(score)),
+                None => continue,
+            },
+            _ => (name.clone(), None),
+        };
+
+        match selected.get(&base) {
+            Some((best, _)) if *best >= score => {}
+            _ => {
+                selected.insert(base, (score, name));
+            }
+        }
+    }
+
+    selected
+        .into_iter()
+        .map(|(base, (_, name))| (base, name))
+        .collect()
+}
+
+/// Picks the best matching alternate among the paths by their base names, keeping the order of
+/// the paths. Returns each selected path together with the name to link it as.
+pub fn select_alternate_paths(
+    paths: Vec<PathBuf>,
+    context: &AlternateContext,
+) -> Vec<(PathBuf, OsString)> {
+    let names = paths
+        .iter()
+        .filter_map(|path| path.file_name())
+        .map(OsStr::to_os_string);
+    let selected = select_alternates(names, context);
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let name = path.file_name()?.to_os_string();
+
+            selected
+                .iter()
+                .find(|(_, selected)| **selected == name)
+                .map(|(base, _)| (path.clone(), base.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_alternates() {
+        let context = AlternateContext {
+            facts: HashMap::from([
+                ("os".to_string(), "linux".to_string()),
+                ("hostname".to_string(), "work".to_string()),
+            ]),
+            classes: vec!["laptop".to_string()],
+        };
+
+        let names = [
+            ".bashrc",
+            ".bashrc##os.Linux",
+            ".bashrc##os.Linux,class.laptop",
+            ".bashrc##os.Darwin,class.laptop",
+            ".gitconfig##default",
+            ".gitconfig##hostname.work",
+            ".vimrc##hostname.home",
+            ".profile",
+        ];
+
+        let selected = select_alternates(names.map(OsString::from), &context);
+
+        assert_eq!(
+            selected,
+            BTreeMap::from([
+                (".bashrc".into(), ".bashrc##os.Linux,class.laptop".into()),
+                (".gitconfig".into(), ".gitconfig##hostname.work".into()),
+                (".profile".into(), ".profile".into()),
+            ])
+        );
+    }
+}
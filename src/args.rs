@@ -1,23 +1,224 @@
-use clap::{ArgGroup, Parser};
+use std::rc::Rc;
+
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+
+use crate::alternates::AlternateContext;
+use crate::permissions::{RequiredPermissions, SourceOwner};
+use crate::plan::Plan;
+use crate::utils::{parse_mode, parse_var};
 
 /// Create symlinks quickly and easily even if there are files there already.
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub args: HookArgs,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Write the links which would be created to an archive or script instead of the filesystem.
+    Export(ExportArgs),
+
+    /// List the operations which are still needed for the links to be in place.
+    Status(StatusArgs),
+
+    /// Propagate changes between sources and their copies made with --copy or --reflink.
+    Sync(SyncArgs),
+
+    /// Remove the links to the sources, leaving anything else at the destination alone.
+    Unlink(UnlinkArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExportArgs {
+    /// The format to export the links as.
+    #[arg(long, value_enum, default_value_t = ExportFormat::Tar)]
+    pub format: ExportFormat,
+
+    /// The file to write the export to. Defaults to stdout.
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// Also include the source files the symlinks point to in a tar archive.
+    #[arg(long)]
+    pub include_sources: bool,
+
+    #[command(flatten)]
+    pub args: HookArgs,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A tar archive containing the symlinks.
+    Tar,
+    /// A POSIX shell script performing the operations.
+    Sh,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct StatusArgs {
+    #[command(flatten)]
+    pub args: HookArgs,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct UnlinkArgs {
+    #[command(flatten)]
+    pub args: HookArgs,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct SyncArgs {
+    /// Don't copy anything, just print what would be done.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Do not print any output except errors and conflicts.
+    #[arg(short, long)]
+    pub quiet: bool,
+}
+
+/// How the destination is made to refer to the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkType {
+    #[default]
+    Symlink,
+    Hard,
+    Copy,
+    Reflink,
+    Template,
+}
+
+#[derive(Args, Debug, Clone)]
 #[command(group(ArgGroup::new("overwrite").multiple(false)))]
+#[command(group(ArgGroup::new("link_type").multiple(false)))]
 #[command(group(ArgGroup::new("logging").multiple(false)))]
 pub struct HookArgs {
     /// The file path where you wish the real files to be.
-    #[arg(short, long, required = true)]
-    pub source: String,
+    /// Pass multiple paths or a glob to link them all into --target-directory,
+    /// or to layer several directories into the destination, later ones taking priority.
+    #[arg(short, long, required_unless_present_any = ["from_stdin", "manifest"], num_args = 1..)]
+    pub source: Vec<String>,
 
     /// The file path where you wish the symlink files to be.
-    #[arg(short, long, required = true)]
-    pub destination: String,
+    #[arg(short, long, required_unless_present_any = ["target_directory", "from_stdin", "manifest"])]
+    pub destination: Option<String>,
+
+    /// Create a symlink named after each source inside this directory.
+    #[arg(short, long, conflicts_with = "destination")]
+    pub target_directory: Option<String>,
+
+    /// When the destination is an existing directory and the source is a file, create the symlink inside it.
+    #[arg(long)]
+    pub into_directory: bool,
+
+    /// The octal mode to give parent directories which are created because they are missing.
+    #[arg(long, value_parser = parse_mode)]
+    pub parent_mode: Option<u32>,
+
+    /// Never remove or move this path or anything containing it, in addition to the built-in protected paths.
+    #[arg(long)]
+    pub protect: Vec<String>,
+
+    /// Ask for confirmation before removing more files than this, even with --force.
+    #[arg(long, default_value_t = 1000)]
+    pub max_delete_files: u64,
+
+    /// Ask for confirmation before removing more bytes than this, even with --force.
+    #[arg(long, default_value_t = 100 * 1024 * 1024)]
+    pub max_delete_bytes: u64,
+
+    /// Read source<TAB>destination pairs from stdin, one per line, and link them all.
+    #[arg(long, conflicts_with_all = ["source", "destination", "target_directory", "interactive"])]
+    pub from_stdin: bool,
+
+    /// Separate the pairs read with --from-stdin by NUL characters instead of newlines.
+    #[arg(short = '0', long, requires = "from_stdin")]
+    pub null: bool,
+
+    /// Link every entry listed in this TOML manifest whose condition holds on this machine.
+    #[arg(short, long, conflicts_with_all = ["source", "destination", "target_directory", "from_stdin"])]
+    pub manifest: Option<String>,
+
+    /// Only link the manifest entries tagged with one of the tags of this profile.
+    #[arg(long, requires = "manifest")]
+    pub profile: Option<String>,
+
+    /// Only link the manifest entries with one of these comma separated tags.
+    #[arg(long, requires = "manifest", value_delimiter = ',')]
+    pub tags: Vec<String>,
+
+    /// Don't link the manifest entries with any of these comma separated tags.
+    #[arg(long, requires = "manifest", value_delimiter = ',')]
+    pub skip_tags: Vec<String>,
+
+    /// Link the manifest entries whose destination isn't writable by running hook again through
+    /// this command, such as sudo, doas or run0, while linking the rest unprivileged.
+    #[arg(long, value_name = "COMMAND", requires = "manifest")]
+    pub escalate: Option<String>,
+
+    /// Only link the manifest entries at these indices, used by --escalate.
+    #[arg(long, hide = true, requires = "manifest", value_delimiter = ',')]
+    pub entries: Vec<usize>,
+
+    /// Host facts to use instead of gathering them, used by --escalate.
+    #[arg(long = "fact", hide = true, value_parser = parse_var)]
+    pub facts: Vec<(String, String)>,
+
+    /// Only link the entries of this manifest package and the packages it requires.
+    /// Can be passed multiple times.
+    #[arg(long, requires = "manifest")]
+    pub package: Vec<String>,
+
+    /// Create hard links instead of symlinks. Only files can be hard linked.
+    #[arg(long, group = "link_type", conflicts_with_all = ["relative", "fold"])]
+    pub hard: bool,
+
+    /// Place a copy of the source at the destination and keep them in sync with `hook sync`.
+    /// Only files can be copied.
+    #[arg(long, group = "link_type", conflicts_with_all = ["relative", "fold"])]
+    pub copy: bool,
+
+    /// Clone the source into the destination so both share their data until one is changed,
+    /// falling back to a copy where cloning isn't supported. Kept in sync like --copy.
+    #[arg(long, group = "link_type", conflicts_with_all = ["relative", "fold"])]
+    pub reflink: bool,
+
+    /// Render the source file into the destination, replacing every `{{ name }}` with the value
+    /// of the variable. Host facts such as `hostname`, `user`, `os` and `arch` are available, as
+    /// well as environment variables as `env.NAME`. Only files can be rendered.
+    #[arg(long, group = "link_type", conflicts_with_all = ["relative", "fold"])]
+    pub template: bool,
+
+    /// Set a variable for --template, overriding host facts, the environment and manifest variables.
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+
+    /// A class of this machine such as `laptop`, selecting alternate files named like
+    /// `.bashrc##class.laptop`. Can be passed multiple times.
+    #[arg(long)]
+    pub class: Vec<String>,
 
     /// Set symlinks as relative to the destination file.
     #[arg(short, long)]
     pub relative: bool,
 
+    /// The owner as USER[:GROUP] to give files moved or created inside the source.
+    /// Defaults to the user who ran hook with sudo.
+    #[arg(long, value_name = "USER[:GROUP]")]
+    pub owner: Option<String>,
+
+    /// Resolve paths inside this directory, while writing symlink targets as they will appear at runtime.
+    #[arg(long)]
+    pub root: Option<String>,
+
     /// When there is the possibility for data loss, ask the user for confirmation.
     #[arg(short, long, group = "overwrite")]
     pub interactive: bool,
@@ -34,7 +235,74 @@ pub struct HookArgs {
     #[arg(short, long, group = "logging")]
     pub verbose: bool,
 
+    /// Link each file inside the source directory individually instead of the directory itself.
+    #[arg(long)]
+    pub per_file: bool,
+
+    /// Link whole directories when possible and unfold them when another source shares them. Implies --per-file.
+    #[arg(long)]
+    pub fold: bool,
+
+    /// Skip files matching this gitignore-style pattern when linking per file. Can be passed multiple times.
+    #[arg(long)]
+    pub ignore: Vec<String>,
+
     /// Don't create symlinks, just print what would be done.
     #[arg(long)]
     pub dry_run: bool,
+
+    /// Records the operations instead of performing them, when exporting.
+    #[arg(skip)]
+    pub plan: Option<Plan>,
+
+    /// Links to a destination with a different name than the source without asking, for
+    /// manifest entries which name their destination on purpose.
+    #[arg(skip)]
+    pub rename: bool,
+
+    /// Removes the links instead of creating them.
+    #[arg(skip)]
+    pub unlink: bool,
+
+    /// The mode and ownership required by a manifest entry for the files it manages.
+    #[arg(skip)]
+    pub permissions: Option<RequiredPermissions>,
+
+    /// The owner given to files moved or created inside the source, resolved once per run.
+    #[arg(skip)]
+    pub source_owner: Option<SourceOwner>,
+
+    /// What alternate files are matched against, shared by every link of the run.
+    #[arg(skip)]
+    pub alternates: Option<Rc<AlternateContext>>,
+}
+
+impl HookArgs {
+    pub fn set_link_type(&mut self, link_type: LinkType) {
+        self.hard = link_type == LinkType::Hard;
+        self.copy = link_type == LinkType::Copy;
+        self.reflink = link_type == LinkType::Reflink;
+        self.template = link_type == LinkType::Template;
+    }
+
+    /// The alternate context of the run, built here if it wasn't yet.
+    pub fn alternate_context(&self) -> Rc<AlternateContext> {
+        self.alternates
+            .clone()
+            .unwrap_or_else(|| Rc::new(AlternateContext::new(self)))
+    }
+
+    pub fn link_type(&self) -> LinkType {
+        if self.hard {
+            LinkType::Hard
+        } else if self.copy {
+            LinkType::Copy
+        } else if self.reflink {
+            LinkType::Reflink
+        } else if self.template {
+            LinkType::Template
+        } else {
+            LinkType::Symlink
+        }
+    }
 }
@@ -0,0 +1,104 @@
+use std::fmt::Display;
+
+use crate::error::HookError;
+
+/// Counts the outcome of every link in a run processing many of them.
+#[derive(Debug, Default)]
+pub struct BatchSummary {
+    done: usize,
+    skipped: usize,
+    failed: usize,
+}
+
+impl BatchSummary {
+    pub fn record(&mut self, result: Result<(), HookError>, link: impl Display, quiet: bool) {
+        match result {
+            Ok(()) => self.done += 1,
+            Err(HookError::Skipping(message)) => {
+                self.skipped += 1;
+
+                if !quiet {
+                    eprintln!("Skipping: {}", message);
+                }
+            }
+            Err(err) => {
+                self.failed += 1;
+                eprintln!("Failed: {} | {}", link, err);
+            }
+        }
+    }
+
+    /// Prints the counts, labelling the links processed with `done`, and fails if any link failed.
+    pub fn finish(self, done: &str, quiet: bool) -> Result<(), HookError> {
+        if !quiet {
+            eprintln!(
+                "{}: {} | Skipped: {} | Failed: {}",
+                done, self.done, self.skipped, self.failed
+            );
+        }
+
+        if self.failed > 0 {
+            return Err(HookError::BatchFailed {
+                failed: self.failed,
+                total: self.done + self.skipped + self.failed,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses `source<TAB>destination` records separated by `separator`. Empty records are skipped.
+pub fn parse_link_pairs(input: &str, separator: char) -> Result<Vec<(String, String)>, HookError> {
+    input
+        .split(separator)
+        .map(|record| record.trim_end_matches('\r'))
+        .enumerate()
+        .filter(|(_, record)| !record.is_empty())
+        .map(|(index, record)| {
+            record
+                .split_once('\t')
+                .map(|(source, destination)| (source.to_string(), destination.to_string()))
+                .ok_or_else(|| {
+                    HookError::ExecutionError(format!(
+                        "Invalid record {}: expected source<TAB>destination",
+                        index + 1
+                    ))
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_link_pairs_lines() {
+        let pairs = parse_link_pairs("a\tb\r\n\nc d\te f\n", '\n').unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                ("a".to_string(), "b".to_string()),
+                ("c d".to_string(), "e f".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_link_pairs_null_delimited() {
+        let pairs = parse_link_pairs("a\nb\tc\0d\te\0", '\0').unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                ("a\nb".to_string(), "c".to_string()),
+                ("d".to_string(), "e".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_link_pairs_missing_tab() {
+        assert!(parse_link_pairs("a\tb\nc\n", '\n').is_err());
+    }
+}
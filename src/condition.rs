@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use crate::facts::{has_executable, FACT_NAMES};
+
+/// Evaluates a `when` condition such as `os == 'linux' && has('nvim')` against the host facts.
+///
+/// Facts are referred to by name and compared to quoted strings with `==` and `!=`.
+/// `has('name')` checks for an executable on `PATH` and `env('NAME')` reads an environment
+/// variable. Conditions can be combined with `&&`, `||`, `!` and parentheses. A bare fact or
+/// string is true when it isn't empty.
+pub fn evaluate(condition: &str, facts: &HashMap<String, String>) -> Result<bool, String> {
+    let tokens = tokenize(condition)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        position: 0,
+        facts,
+    };
+
+    let value = parser.or()?;
+
+    match parser.tokens.get(parser.position) {
+        None => Ok(value.is_true()),
+        Some(token) => Err(format!("Unexpected {}", token)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Name(String),
+    String(String),
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Name(name) => write!(f, "{}", name),
+            Token::String(string) => write!(f, "'{}'", string),
+            Token::Equal => write!(f, "=="),
+            Token::NotEqual => write!(f, "!="),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+fn tokenize(condition: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = condition.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '=' if chars.next_if_eq(&'=').is_some() => Token::Equal,
+            '!' if chars.next_if_eq(&'=').is_some() => Token::NotEqual,
+            '!' => Token::Not,
+            '&' if chars.next_if_eq(&'&').is_some() => Token::And,
+            '|' if chars.next_if_eq(&'|').is_some() => Token::Or,
+            '\'' | '"' => {
+                let mut string = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some(c) => string.push(c),
+                        None => return Err("Unclosed string".to_string()),
+                    }
+                }
+
+                Token::String(string)
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut name = c.to_string();
+
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    name.push(c);
+                }
+
+                Token::Name(name)
+            }
+            c => return Err(format!("Unexpected character: {}", c)),
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+enum Value {
+    String(String),
+    Bool(bool),
+}
+
+impl Value {
+    fn is_true(&self) -> bool {
+        match self {
+            Value::String(string) => !string.is_empty(),
+            Value::Bool(value) => *value,
+        }
+    }
+
+    fn into_string(self) -> String {
+        match self {
+            Value::String(string) => string,
+            Value::Bool(value) => value.to_string(),
+        }
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    facts: &'a HashMap<String, String>,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn next_if(&mut self, expected: &Token) -> bool {
+        let matches = self.tokens.get(self.position) == Some(expected);
+
+        if matches {
+            self.position += 1;
+        }
+
+        matches
+    }
+
+    fn expect(&mut self, expected: &Token) -> Result<(), String> {
+        if self.next_if(expected) {
+            Ok(())
+        } else {
+            Err(format!("Expected {}", expected))
+        }
+    }
+
+    fn or(&mut self) -> Result<Value, String> {
+        let mut value = self.and()?.is_true();
+
+        while self.next_if(&Token::Or) {
+            // Both sides are always parsed so errors aren't hidden by short circuiting.
+            let right = self.and()?.is_true();
+            value = value || right;
+        }
+
+        Ok(Value::Bool(value))
+    }
+
+    fn and(&mut self) -> Result<Value, String> {
+        let mut value = self.comparison()?.is_true();
+
+        while self.next_if(&Token::And) {
+            let right = self.comparison()?.is_true();
+            value = value && right;
+        }
+
+        Ok(Value::Bool(value))
+    }
+
+    fn comparison(&mut self) -> Result<Value, String> {
+        let left = self.unary()?;
+
+        if self.next_if(&Token::Equal) {
+            let right = self.unary()?;
+            return Ok(Value::Bool(left.into_string() == right.into_string()));
+        }
+
+        if self.next_if(&Token::NotEqual) {
+            let right = self.unary()?;
+            return Ok(Value::Bool(left.into_string() != right.into_string()));
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Value, String> {
+        if self.next_if(&Token::Not) {
+            return Ok(Value::Bool(!self.unary()?.is_true()));
+        }
+
+        match self.next().cloned() {
+            Some(Token::Open) => {
+                let value = self.or()?;
+                self.expect(&Token::Close)?;
+                Ok(value)
+            }
+            Some(Token::String(string)) => Ok(Value::String(string)),
+            Some(Token::Name(name)) if self.next_if(&Token::Open) => {
+                let Some(Token::String(argument)) = self.next().cloned() else {
+                    return Err(format!("Expected a quoted argument for {}", name));
+                };
+
+                self.expect(&Token::Close)?;
+
+                match name.as_str() {
+                    "has" => Ok(Value::Bool(has_executable(&argument))),
+                    "env" => Ok(Value::String(std::env::var(argument).unwrap_or_default())),
+                    _ => Err(format!("Unknown function: {}", name)),
+                }
+            }
+            Some(Token::Name(name)) => match self.facts.get(&name) {
+                Some(value) => Ok(Value::String(value.clone())),
+                None if FACT_NAMES.contains(&name.as_str()) => Ok(Value::String(String::new())),
+                None => Err(format!("Unknown fact: {}", name)),
+            },
+            Some(token) => Err(format!("Unexpected {}", token)),
+            None => Err("Unexpected end of condition".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let facts = HashMap::from([
+            ("os".to_string(), "linux".to_string()),
+            ("hostname".to_string(), "work".to_string()),
+        ]);
+
+        assert!(evaluate("os == 'linux'", &facts).unwrap());
+        assert!(evaluate("os == \"linux\" && hostname != 'home'", &facts).unwrap());
+        assert!(evaluate("!(os == 'windows' || hostname == 'home')", &facts).unwrap());
+        assert!(!evaluate("distro", &facts).unwrap());
+        assert!(!evaluate("has('surely-not-an-executable')", &facts).unwrap());
+        assert!(evaluate("planet == 'earth'", &facts).is_err());
+        assert!(evaluate("os == 'linux' &&", &facts).is_err());
+        assert!(evaluate("(os == 'linux'", &facts).is_err());
+    }
+}
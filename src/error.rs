@@ -1,5 +1,6 @@
 use std::{fmt::Display, path::PathBuf};
 
+#[derive(Debug)]
 pub enum HookError {
     ExecutionError(String),
     SymlinkCreationError(std::io::Error),
@@ -8,6 +9,38 @@ pub enum HookError {
     PathsDontExist,
     DifferentNames,
     CancelledByUser,
+    BatchFailed {
+        failed: usize,
+        total: usize,
+    },
+    NestedPaths {
+        source: PathBuf,
+        destination: PathBuf,
+    },
+    ProtectedPath(PathBuf),
+    FilesOnly(PathBuf),
+    HardLinkCrossDevice {
+        source: PathBuf,
+        destination: PathBuf,
+    },
+    Pending(usize),
+    InvalidManifest {
+        path: PathBuf,
+        message: String,
+    },
+    UnknownProfile(String),
+    UnknownPackage(String),
+    UnknownOwner(String),
+    DependencyCycle(Vec<String>),
+    CommandFailed {
+        command: String,
+        status: String,
+    },
+    InvalidCondition {
+        condition: String,
+        message: String,
+    },
+    SyncConflicts(usize),
     PathDiff {
         source: PathBuf,
         destination: PathBuf,
@@ -27,6 +60,20 @@ impl Display for HookError {
             HookError::PathsDontExist => write!(f, "The source and destination paths don't exist."),
             HookError::DifferentNames => write!(f, "The source and destination paths have different base names."),
             HookError::CancelledByUser => write!(f, "The operation was cancelled by the user."),
+            HookError::BatchFailed { failed, total } => write!(f, "{} of {} links failed.", failed, total),
+            HookError::NestedPaths { source, destination } => write!(f, "The source and destination paths are the same or inside each other | Source: {} | Destination: {}", source.display(), destination.display()),
+            HookError::ProtectedPath(path) => write!(f, "Refusing to remove or move a protected path: {}", path.display()),
+            HookError::FilesOnly(path) => write!(f, "Only files can be hard linked, copied or rendered, pass --per-file to link the files inside a directory: {}", path.display()),
+            HookError::HardLinkCrossDevice { source, destination } => write!(f, "Hard links can't be created across filesystems | Source: {} | Destination: {}", source.display(), destination.display()),
+            HookError::Pending(count) => write!(f, "Pending operations: {}", count),
+            HookError::InvalidManifest { path, message } => write!(f, "Invalid manifest {}: {}", path.display(), message),
+            HookError::UnknownProfile(profile) => write!(f, "The manifest has no profile named {}", profile),
+            HookError::UnknownOwner(name) => write!(f, "No user or group is named {}", name),
+            HookError::UnknownPackage(package) => write!(f, "The manifest has no package named {}", package),
+            HookError::CommandFailed { command, status } => write!(f, "The command `{}` failed: {}", command, status),
+            HookError::DependencyCycle(cycle) => write!(f, "Package dependencies form a cycle: {}", cycle.join(" -> ")),
+            HookError::InvalidCondition { condition, message } => write!(f, "Invalid condition `{}`: {}", condition, message),
+            HookError::SyncConflicts(count) => write!(f, "Conflicting copies which were changed on both sides: {}", count),
             HookError::PathDiff { source, destination } => write!(f, "Couldn't compute difference between {} and {}", source.display(), destination.display()),
             HookError::Debug(message) => write!(f, "Debug: {}", message),
         }
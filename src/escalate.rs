@@ -0,0 +1,279 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::{args::HookArgs, error::HookError, permissions::is_root};
+
+/// Whether linking at the destination needs more privileges than hook has, because the directory
+/// the link is created in, or the destination directory itself, isn't writable.
+pub fn needs_privileges(destination: &Path) -> bool {
+    if is_root() {
+        return false;
+    }
+
+    let parent = destination
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| ancestor.exists());
+
+    let destination_directory = destination.is_dir() && !destination.is_symlink();
+
+    parent.is_some_and(|parent| !is_writable(parent))
+        || (destination_directory && !is_writable(destination))
+}
+
+fn is_writable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+            return false;
+        };
+
+        // SAFETY: the path is a valid C string.
+        unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+    }
+
+    #[cfg(not(unix))]
+    {
+        path.metadata()
+            .is_ok_and(|metadata| !metadata.permissions().readonly())
+    }
+}
+
+/// Links the manifest entries at the given indices by running hook again through the escalation
+/// command, such as `sudo` or `doas`, with the same options and home directory. Files moved into
+/// the source are given to the current user rather than root.
+pub fn run_escalated(
+    escalate: &str,
+    manifest: &Path,
+    entries: &[usize],
+    args: &HookArgs,
+) -> Result<(), HookError> {
+    let mut escalate_command = escalate.split_whitespace();
+
+    let Some(program) = escalate_command.next() else {
+        return Err(HookError::ExecutionError(
+            "The escalation command is empty".to_string(),
+        ));
+    };
+
+    let exe = std::env::current_exe().map_err(|err| {
+        HookError::ExecutionError(format!("Error finding the hook executable: {}", err))
+    })?;
+
+    let mut command = Command::new(program);
+    command.args(escalate_command);
+
+    // Escalation commands usually reset the environment, while `~` destinations are resolved
+    // against HOME and the state of copies and templates is kept in the data directory.
+    command.arg("env");
+
+    for name in ["HOME", "XDG_DATA_HOME"] {
+        if let Some(value) = std::env::var_os(name) {
+            let mut var = OsString::from(format!("{}=", name));
+            var.push(value);
+            command.arg(var);
+        }
+    }
+
+    command.arg(exe);
+
+    if args.unlink {
+        command.arg("unlink");
+    }
+
+    command
+        .arg("--manifest")
+        .arg(manifest)
+        .arg("--entries")
+        .arg(
+            entries
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+        )
+        .args(forwarded_args(args));
+
+    if !args.quiet {
+        eprintln!("Escalating with {}: {} links", escalate, entries.len());
+    }
+
+    let status = command
+        .stdin(Stdio::inherit())
+        .status()
+        .map_err(|err| HookError::ExecutionError(format!("Error running {}: {}", program, err)))?;
+
+    if !status.success() {
+        return Err(HookError::CommandFailed {
+            command: escalate.to_string(),
+            status: status.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// The options of this run which apply to every entry, as arguments for the escalated run.
+fn forwarded_args(args: &HookArgs) -> Vec<OsString> {
+    let mut forwarded = Vec::<OsString>::new();
+
+    let flags = [
+        ("--force", args.force),
+        ("--interactive", args.interactive),
+        ("--quiet", args.quiet),
+        ("--verbose", args.verbose),
+        ("--relative", args.relative),
+        ("--per-file", args.per_file),
+        ("--fold", args.fold),
+        ("--into-directory", args.into_directory),
+    ];
+
+    for (flag, set) in flags {
+        if set {
+            forwarded.push(flag.into());
+        }
+    }
+
+    let mut option = |name: &str, value: String| {
+        forwarded.push(name.into());
+        forwarded.push(value.into());
+    };
+
+    option("--max-delete-files", args.max_delete_files.to_string());
+    option("--max-delete-bytes", args.max_delete_bytes.to_string());
+
+    if let Some(mode) = args.parent_mode {
+        option("--parent-mode", format!("{:o}", mode));
+    }
+
+    if let Some(root) = &args.root {
+        option("--root", root.clone());
+    }
+
+    for path in &args.protect {
+        option("--protect", path.clone());
+    }
+
+    for pattern in &args.ignore {
+        option("--ignore", pattern.clone());
+    }
+
+    for class in &args.class {
+        option("--class", class.clone());
+    }
+
+    for (name, value) in &args.vars {
+        option("--var", format!("{}={}", name, value));
+    }
+
+    // Facts such as `user` change as root, while alternates and templates should see this run's.
+    let context = args.alternate_context();
+    let facts = context.facts().iter().collect::<BTreeMap<_, _>>();
+
+    for (name, value) in facts {
+        option("--fact", format!("{}={}", name, value));
+    }
+
+    match &args.owner {
+        Some(owner) => option("--owner", owner.clone()),
+        None => {
+            if let Some(owner) = current_user() {
+                option("--owner", owner);
+            }
+        }
+    }
+
+    forwarded
+}
+
+fn current_user() -> Option<String> {
+    #[cfg(unix)]
+    {
+        // SAFETY: getuid and getgid have no preconditions.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        Some(format!("{}:{}", uid, gid))
+    }
+
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::args::Cli;
+
+    #[test]
+    fn test_forwarded_args() {
+        let cli = Cli::parse_from([
+            "hook",
+            "--manifest",
+            "hook.toml",
+            "--escalate",
+            "sudo -n",
+            "--profile",
+            "server",
+            "--force",
+            "--into-directory",
+            "--var",
+            "email=me@example.com",
+            "--class",
+            "laptop",
+            "--owner",
+            "1000:1000",
+            "--fact",
+            "user=me",
+        ]);
+
+        let forwarded = forwarded_args(&cli.args)
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect::<Vec<_>>();
+
+        let has = |pair: &[&str]| forwarded.windows(pair.len()).any(|window| window == pair);
+
+        assert!(has(&["--force"]));
+        assert!(has(&["--into-directory"]));
+        assert!(has(&["--var", "email=me@example.com"]));
+        assert!(has(&["--class", "laptop"]));
+        assert!(has(&["--owner", "1000:1000"]));
+        assert!(has(&["--fact", "user=me"]));
+
+        // The parent already selected the entries and only escalates once.
+        assert!(!forwarded.iter().any(|arg| arg == "--profile"));
+        assert!(!forwarded.iter().any(|arg| arg == "--escalate"));
+    }
+
+    #[test]
+    fn test_needs_privileges() {
+        let directory = std::env::temp_dir().join(format!("hook-escalate-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        assert!(!needs_privileges(&directory.join("missing/parents/file")));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let read_only = directory.join("read-only");
+            std::fs::create_dir(&read_only).unwrap();
+            std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+            // Root can write anywhere, so it never needs to escalate.
+            assert_eq!(needs_privileges(&read_only.join("file")), !is_root());
+            assert_eq!(needs_privileges(&read_only), !is_root());
+        }
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,444 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::Write,
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tar::{Builder, EntryType, Header};
+
+use crate::error::HookError;
+use crate::plan::Step;
+use crate::utils::strip_root;
+
+/// Writes the layout the steps would create as a tar archive. Paths are stored as they will
+/// appear at runtime, relative to the archive root. Removals can't be represented in an archive
+/// and are left out, while modes and owners are set on the entries of their paths.
+pub fn write_tar(
+    steps: &[Step],
+    root: Option<&Path>,
+    include_sources: bool,
+    output: impl Write,
+) -> Result<(), HookError> {
+    let mut builder = Builder::new(output);
+    builder.follow_symlinks(false);
+
+    let mtime = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+
+    let mut added_sources = HashSet::new();
+
+    // An entry can't be changed once it's written, so the permissions are gathered up front.
+    let mut permissions = HashMap::<&Path, EntryPermissions>::new();
+
+    for step in steps {
+        match step {
+            Step::SetMode { path, mode } => {
+                permissions.entry(path).or_default().mode = Some(*mode);
+            }
+            Step::SetOwner { path, uid, gid } => {
+                let entry = permissions.entry(path).or_default();
+                entry.uid = uid.or(entry.uid);
+                entry.gid = gid.or(entry.gid);
+            }
+            _ => {}
+        }
+    }
+
+    let permissions_of = |path: &Path| permissions.get(path).copied().unwrap_or_default();
+
+    for step in steps {
+        match step {
+            Step::CreateDirectory(path) => {
+                let mut header = Header::new_gnu();
+                header.set_entry_type(EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_mtime(mtime);
+                header.set_size(0);
+                permissions_of(path).apply(&mut header);
+
+                builder
+                    .append_data(&mut header, archive_path(path, root), std::io::empty())
+                    .map_err(archive_error)?;
+            }
+            Step::MoveFile { from, to } | Step::MoveDirectory { from, to }
+                if include_sources && added_sources.insert(to.clone()) =>
+            {
+                append_source(
+                    &mut builder,
+                    from,
+                    &archive_path(to, root),
+                    permissions_of(to),
+                )?;
+            }
+            Step::Symlink {
+                source,
+                target,
+                destination,
+                ..
+            } => {
+                if include_sources && source.exists() && added_sources.insert(source.clone()) {
+                    let permissions = permissions_of(source);
+                    append_source(
+                        &mut builder,
+                        source,
+                        &archive_path(source, root),
+                        permissions,
+                    )?;
+                }
+
+                let mut header = Header::new_gnu();
+                header.set_entry_type(EntryType::Symlink);
+                header.set_mode(0o777);
+                header.set_mtime(mtime);
+                header.set_size(0);
+
+                builder
+                    .append_link(&mut header, archive_path(destination, root), target)
+                    .map_err(archive_error)?;
+            }
+            Step::Copy {
+                source,
+                destination,
+                ..
+            } => {
+                let permissions = permissions_of(destination);
+                append_source(
+                    &mut builder,
+                    source,
+                    &archive_path(destination, root),
+                    permissions,
+                )?;
+            }
+            Step::Render {
+                source,
+                destination,
+                content,
+            } => {
+                let mut header = Header::new_gnu();
+                header.set_entry_type(EntryType::Regular);
+                header.set_mode(source_mode(source).unwrap_or(0o644));
+                header.set_mtime(mtime);
+                header.set_size(content.len() as u64);
+                permissions_of(destination).apply(&mut header);
+
+                builder
+                    .append_data(
+                        &mut header,
+                        archive_path(destination, root),
+                        content.as_bytes(),
+                    )
+                    .map_err(archive_error)?;
+            }
+            Step::HardLink {
+                source,
+                destination,
+            } => {
+                // A hard link entry needs the file it links to inside the archive.
+                if source.exists() && added_sources.insert(source.clone()) {
+                    let permissions = permissions_of(source);
+                    append_source(
+                        &mut builder,
+                        source,
+                        &archive_path(source, root),
+                        permissions,
+                    )?;
+                }
+
+                let mut header = Header::new_gnu();
+                header.set_entry_type(EntryType::Link);
+                header.set_mtime(mtime);
+                header.set_size(0);
+
+                builder
+                    .append_link(
+                        &mut header,
+                        archive_path(destination, root),
+                        archive_path(source, root),
+                    )
+                    .map_err(archive_error)?;
+            }
+            _ => {}
+        }
+    }
+
+    builder.finish().map_err(archive_error)
+}
+
+/// Writes the steps as a POSIX shell script performing the same operations. Every operation is
+/// guarded so the script stops instead of overwriting anything which changed since the export.
+pub fn write_script(
+    steps: &[Step],
+    root: Option<&Path>,
+    mut output: impl Write,
+) -> Result<(), HookError> {
+    let quote = |path: &Path| {
+        let path = match root {
+            Some(root) => strip_root(path, root),
+            None => path.to_path_buf(),
+        };
+
+        quote_shell(&path.to_string_lossy())
+    };
+
+    let mut script = String::from(
+        "#!/bin/sh\n# Generated by hook.\nset -eu\n\nfail() {\n    echo \"hook: $*\" >&2\n    exit 1\n}\n",
+    );
+
+    for step in steps {
+        script.push('\n');
+
+        match step {
+            Step::CreateDirectory(path) => {
+                let path = quote(path);
+                script.push_str(&format!("[ -d {path} ] || mkdir -p -- {path}\n"));
+            }
+            Step::RemoveFile(path) => {
+                let path = quote(path);
+                script.push_str(&format!(
+                    "[ ! -d {path} ] || [ -L {path} ] || fail {path} is a directory\n"
+                ));
+                script.push_str(&format!("rm -f -- {path}\n"));
+            }
+            Step::RemoveDirectory(path) => {
+                let path = quote(path);
+                script.push_str(&format!(
+                    "[ -d {path} ] && [ ! -L {path} ] || fail {path} is not a directory\n"
+                ));
+                script.push_str(&format!("rm -rf -- {path}\n"));
+            }
+            Step::MoveFile { from, to } | Step::MoveDirectory { from, to } => {
+                let (from, to) = (quote(from), quote(to));
+                script.push_str(&format!("[ -e {from} ] || fail {from} does not exist\n"));
+                script.push_str(&format!(
+                    "[ ! -e {to} ] && [ ! -L {to} ] || fail {to} already exists\n"
+                ));
+                script.push_str(&format!("mv -- {from} {to}\n"));
+            }
+            Step::Symlink {
+                target,
+                destination,
+                ..
+            } => {
+                let target = quote_shell(&target.to_string_lossy());
+                let destination = quote(destination);
+                script.push_str(&format!(
+                    "[ ! -e {destination} ] && [ ! -L {destination} ] || fail {destination} already exists\n"
+                ));
+                script.push_str(&format!("ln -s -- {target} {destination}\n"));
+            }
+            Step::HardLink {
+                source,
+                destination,
+            } => {
+                let (source, destination) = (quote(source), quote(destination));
+                script.push_str(&format!("[ -f {source} ] || fail {source} is not a file\n"));
+                script.push_str(&format!(
+                    "[ ! -e {destination} ] && [ ! -L {destination} ] || fail {destination} already exists\n"
+                ));
+                script.push_str(&format!("ln -- {source} {destination}\n"));
+            }
+            Step::Copy {
+                source,
+                destination,
+                reflink,
+            } => {
+                let (source, destination) = (quote(source), quote(destination));
+                script.push_str(&format!("[ -f {source} ] || fail {source} is not a file\n"));
+                script.push_str(&format!(
+                    "[ ! -e {destination} ] && [ ! -L {destination} ] || fail {destination} already exists\n"
+                ));
+                // Cloning needs GNU cp, which falls back to copying with --reflink=auto.
+                let options = if *reflink { "-p --reflink=auto" } else { "-p" };
+                script.push_str(&format!("cp {options} -- {source} {destination}\n"));
+            }
+            Step::SetMode { path, mode } => {
+                script.push_str(&format!("chmod {:04o} -- {}\n", mode, quote(path)));
+            }
+            Step::SetOwner { path, uid, gid } => {
+                let owner = match (uid, gid) {
+                    (Some(uid), Some(gid)) => format!("{}:{}", uid, gid),
+                    (Some(uid), None) => uid.to_string(),
+                    (None, Some(gid)) => format!(":{}", gid),
+                    (None, None) => continue,
+                };
+
+                script.push_str(&format!("chown {} -- {}\n", owner, quote(path)));
+            }
+            Step::Render {
+                source,
+                destination,
+                content,
+            } => {
+                let destination = quote(destination);
+                script.push_str(&format!(
+                    "[ ! -e {destination} ] && [ ! -L {destination} ] || fail {destination} already exists\n"
+                ));
+                script.push_str(&format!(
+                    "printf '%s' {} > {destination}\n",
+                    quote_shell(content)
+                ));
+
+                if let Some(mode) = source_mode(source) {
+                    script.push_str(&format!("chmod {:04o} -- {}\n", mode, destination));
+                }
+            }
+        }
+    }
+
+    output
+        .write_all(script.as_bytes())
+        .map_err(|err| HookError::ExecutionError(format!("Error writing script: {}", err)))
+}
+
+fn quote_shell(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// The mode and owner set on a path by later steps.
+#[derive(Debug, Clone, Copy, Default)]
+struct EntryPermissions {
+    mode: Option<u32>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+
+impl EntryPermissions {
+    fn is_empty(&self) -> bool {
+        self.mode.is_none() && self.uid.is_none() && self.gid.is_none()
+    }
+
+    fn apply(&self, header: &mut Header) {
+        if let Some(mode) = self.mode {
+            header.set_mode(mode);
+        }
+
+        if let Some(uid) = self.uid {
+            header.set_uid(uid.into());
+        }
+
+        if let Some(gid) = self.gid {
+            header.set_gid(gid.into());
+        }
+    }
+}
+
+fn append_source(
+    builder: &mut Builder<impl Write>,
+    path: &Path,
+    archive_path: &Path,
+    permissions: EntryPermissions,
+) -> Result<(), HookError> {
+    if path.is_dir() {
+        return builder
+            .append_dir_all(archive_path, path)
+            .map_err(archive_error);
+    }
+
+    if permissions.is_empty() {
+        return builder
+            .append_path_with_name(path, archive_path)
+            .map_err(archive_error);
+    }
+
+    let file = File::open(path).map_err(archive_error)?;
+    let metadata = file.metadata().map_err(archive_error)?;
+
+    let mut header = Header::new_gnu();
+    header.set_metadata(&metadata);
+    permissions.apply(&mut header);
+
+    builder
+        .append_data(&mut header, archive_path, file)
+        .map_err(archive_error)
+}
+
+/// The mode of the template, which the rendered file keeps.
+fn source_mode(source: &Path) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        source
+            .metadata()
+            .ok()
+            .map(|metadata| metadata.permissions().mode() & 0o7777)
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = source;
+        None
+    }
+}
+
+fn archive_path(path: &Path, root: Option<&Path>) -> PathBuf {
+    let path = match root {
+        Some(root) => strip_root(path, root),
+        None => path.to_path_buf(),
+    };
+
+    path.components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect()
+}
+
+fn archive_error(err: std::io::Error) -> HookError {
+    HookError::ExecutionError(format!("Error writing archive: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_script() {
+        let steps = vec![
+            Step::CreateDirectory(PathBuf::from("/image/home/user/.config")),
+            Step::Symlink {
+                source: PathBuf::from("/image/home/user/dotfiles/it's"),
+                target: PathBuf::from("/home/user/dotfiles/it's"),
+                destination: PathBuf::from("/image/home/user/.config/it's"),
+                directory: false,
+            },
+        ];
+
+        let mut output = Vec::new();
+        write_script(&steps, Some(Path::new("/image")), &mut output).unwrap();
+        let script = String::from_utf8(output).unwrap();
+
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(
+            script.contains("[ -d '/home/user/.config' ] || mkdir -p -- '/home/user/.config'\n")
+        );
+        assert!(script
+            .contains("ln -s -- '/home/user/dotfiles/it'\\''s' '/home/user/.config/it'\\''s'\n"));
+    }
+
+    #[test]
+    fn test_write_tar_permissions() {
+        let steps = vec![
+            Step::Render {
+                source: PathBuf::from("/dotfiles/netrc"),
+                destination: PathBuf::from("/image/home/user/.netrc"),
+                content: "machine example.com".to_string(),
+            },
+            Step::SetMode {
+                path: PathBuf::from("/image/home/user/.netrc"),
+                mode: 0o600,
+            },
+        ];
+
+        let mut output = Vec::new();
+        write_tar(&steps, Some(Path::new("/image")), false, &mut output).unwrap();
+
+        let mut archive = tar::Archive::new(output.as_slice());
+        let entry = archive.entries().unwrap().next().unwrap().unwrap();
+
+        assert_eq!(entry.path().unwrap(), Path::new("home/user/.netrc"));
+        assert_eq!(entry.header().mode().unwrap(), 0o600);
+    }
+}
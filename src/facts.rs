@@ -0,0 +1,107 @@
+use std::path::Path;
+
+/// The names of the facts which can be gathered, even if a fact is unknown on this machine.
+pub const FACT_NAMES: [&str; 5] = ["os", "arch", "distro", "hostname", "user"];
+
+/// Facts about the machine hook is running on, as `(name, value)` pairs. The distro is the `ID`
+/// from `/etc/os-release` such as `arch` or `ubuntu`.
+pub fn host_facts() -> Vec<(String, String)> {
+    let mut facts = vec![
+        ("os".to_string(), std::env::consts::OS.to_string()),
+        ("arch".to_string(), std::env::consts::ARCH.to_string()),
+    ];
+
+    if let Some(distro) = distro() {
+        facts.push(("distro".to_string(), distro));
+    }
+
+    if let Some(hostname) = hostname() {
+        facts.push(("hostname".to_string(), hostname));
+    }
+
+    if let Some(user) = username() {
+        facts.push(("user".to_string(), user));
+    }
+
+    facts
+}
+
+/// Whether an executable with this name is found in one of the directories on `PATH`.
+pub fn has_executable(name: &str) -> bool {
+    let Some(path) = std::env::var_os("PATH") else {
+        return false;
+    };
+
+    std::env::split_paths(&path).any(|directory| {
+        let candidate = directory.join(name);
+
+        is_executable(&candidate)
+            || (cfg!(windows) && is_executable(&candidate.with_extension("exe")))
+    })
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        path.metadata()
+            .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+    }
+
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+fn distro() -> Option<String> {
+    let os_release = std::fs::read_to_string("/etc/os-release")
+        .or_else(|_| std::fs::read_to_string("/usr/lib/os-release"))
+        .ok()?;
+
+    os_release.lines().find_map(|line| {
+        line.strip_prefix("ID=")
+            .map(|id| id.trim_matches(['"', '\'']).to_string())
+    })
+}
+
+fn hostname() -> Option<String> {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|hostname| hostname.trim().to_string())
+        .filter(|hostname| !hostname.is_empty())
+}
+
+fn username() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .filter(|user| !user.is_empty())
+        .or_else(passwd_username)
+}
+
+#[cfg(target_os = "linux")]
+fn passwd_username() -> Option<String> {
+    // SAFETY: the entry is only read before any other call which could overwrite it.
+    unsafe {
+        let entry = libc::getpwuid(libc::geteuid());
+
+        if entry.is_null() {
+            return None;
+        }
+
+        std::ffi::CStr::from_ptr((*entry).pw_name)
+            .to_str()
+            .ok()
+            .map(str::to_string)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn passwd_username() -> Option<String> {
+    None
+}
@@ -0,0 +1,208 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::HookError;
+use crate::utils::get_path;
+
+/// Rejects source and destination paths which are the same or nested inside each other, since
+/// moving or removing one of them would then recurse into or destroy the other, and the
+/// resulting symlink would point to itself or one of its ancestors.
+///
+/// When linking per file the source may live inside the destination, like a dotfiles
+/// repository inside the home directory, since only the files inside the source are linked.
+pub fn check_paths_not_nested(
+    source: &Path,
+    destination: &Path,
+    allow_source_in_destination: bool,
+) -> Result<(), HookError> {
+    // The source is followed through symlinks since that is where the files really are, while
+    // the destination is where the symlink itself will be.
+    let real_source = resolve_path(source);
+    let real_destination = resolve_location(destination);
+
+    if resolve_location(source) == real_destination
+        || (!allow_source_in_destination && real_source.starts_with(&real_destination))
+        || real_destination.starts_with(&real_source)
+    {
+        return Err(HookError::NestedPaths {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Rejects hard linking across filesystems, which isn't possible. Paths which don't exist yet are
+/// checked by their closest existing ancestor.
+pub fn check_same_device(source: &Path, destination: &Path) -> Result<(), HookError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let device = |path: &Path| {
+            path.ancestors()
+                .find_map(|ancestor| ancestor.metadata().ok())
+                .map(|metadata| metadata.dev())
+        };
+
+        let destination_parent = destination.parent().unwrap_or(destination);
+
+        if device(source) != device(destination_parent) {
+            return Err(HookError::HardLinkCrossDevice {
+                source: source.to_path_buf(),
+                destination: destination.to_path_buf(),
+            });
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = (source, destination);
+
+    Ok(())
+}
+
+/// Rejects removing or moving paths which are protected, either built-in, passed with
+/// `--protect`, or a mount point. A path is also protected if it contains a protected path.
+pub fn check_not_protected(path: &Path, extra_paths: &[String]) -> Result<(), HookError> {
+    let location = resolve_location(path);
+
+    let mut protected_paths = builtin_protected_paths();
+
+    for extra_path in extra_paths {
+        let extra_path = get_path(extra_path).map_err(|err| {
+            HookError::ExecutionError(format!("Error getting protected path: {}", err))
+        })?;
+
+        protected_paths.push(extra_path);
+    }
+
+    let is_protected = protected_paths
+        .iter()
+        .any(|protected_path| resolve_path(protected_path).starts_with(&location));
+
+    if is_protected || is_mount_point(&location) {
+        return Err(HookError::ProtectedPath(path.to_path_buf()));
+    }
+
+    Ok(())
+}
+
+fn builtin_protected_paths() -> Vec<PathBuf> {
+    #[cfg(unix)]
+    let mut paths = [
+        "/", "/bin", "/boot", "/dev", "/etc", "/home", "/lib", "/opt", "/proc", "/root", "/sbin",
+        "/sys", "/usr", "/var",
+    ]
+    .map(PathBuf::from)
+    .to_vec();
+
+    #[cfg(not(unix))]
+    let mut paths = [
+        r"C:\",
+        r"C:\Program Files",
+        r"C:\Program Files (x86)",
+        r"C:\Users",
+        r"C:\Windows",
+    ]
+    .map(PathBuf::from)
+    .to_vec();
+
+    paths.extend(dirs::home_dir());
+    paths
+}
+
+#[cfg(unix)]
+fn is_mount_point(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let Some(parent) = path.parent() else {
+        return true;
+    };
+
+    match (path.symlink_metadata(), parent.metadata()) {
+        (Ok(metadata), Ok(parent_metadata)) => {
+            metadata.is_dir() && metadata.dev() != parent_metadata.dev()
+        }
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_mount_point(path: &Path) -> bool {
+    path.parent().is_none()
+}
+
+/// Counts the files and bytes which would be deleted by removing the path, without following
+/// symlinks.
+pub fn measure_removal(path: &Path) -> (u64, u64) {
+    let Ok(metadata) = path.symlink_metadata() else {
+        return (0, 0);
+    };
+
+    if !metadata.is_dir() {
+        return (1, metadata.len());
+    }
+
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return (0, 0);
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| measure_removal(&entry.path()))
+        .fold((0, 0), |(files, bytes), (entry_files, entry_bytes)| {
+            (files + entry_files, bytes + entry_bytes)
+        })
+}
+
+/// Resolves the path without following it if it is a symlink itself.
+fn resolve_location(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => resolve_path(parent).join(name),
+        _ => resolve_path(path),
+    }
+}
+
+/// Canonicalizes the longest existing part of the path and appends the rest as it is.
+fn resolve_path(path: &Path) -> PathBuf {
+    for ancestor in path.ancestors() {
+        if let Ok(resolved) = ancestor.canonicalize() {
+            let rest = path.strip_prefix(ancestor).unwrap_or(Path::new(""));
+            return resolved.join(rest);
+        }
+    }
+
+    path.to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_paths() {
+        let root = std::env::temp_dir().join("hook-guard-test");
+
+        assert!(check_paths_not_nested(&root.join("a"), &root.join("a"), false).is_err());
+        assert!(check_paths_not_nested(&root.join("a"), &root.join("a/b"), false).is_err());
+        assert!(check_paths_not_nested(&root.join("a/b"), &root.join("a"), false).is_err());
+        assert!(check_paths_not_nested(&root.join("a"), &root.join("ab"), false).is_ok());
+        assert!(check_paths_not_nested(&root.join("a/c"), &root.join("b/c"), false).is_ok());
+
+        assert!(check_paths_not_nested(&root.join("a/b"), &root.join("a"), true).is_ok());
+        assert!(check_paths_not_nested(&root.join("a"), &root.join("a/b"), true).is_err());
+        assert!(check_paths_not_nested(&root.join("a"), &root.join("a"), true).is_err());
+    }
+
+    #[test]
+    fn test_protected_paths() {
+        let home = dirs::home_dir().unwrap();
+
+        assert!(check_not_protected(&home, &[]).is_err());
+        assert!(check_not_protected(home.parent().unwrap(), &[]).is_err());
+        assert!(
+            check_not_protected(&home.join("Documents"), &["~/Documents".to_string()]).is_err()
+        );
+        assert!(check_not_protected(&home.join(".hook-guard-test"), &[]).is_ok());
+    }
+}
@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::error::HookError;
+
+pub const IGNORE_FILE_NAME: &str = ".hookignore";
+
+/// Builds gitignore-style rules from the `.hookignore` file of every source and the extra
+/// patterns passed on the command line. Paths are matched relative to the source roots.
+pub fn build_ignore(sources: &[PathBuf], patterns: &[String]) -> Result<Gitignore, HookError> {
+    let mut builder = GitignoreBuilder::new("");
+
+    builder
+        .add_line(None, IGNORE_FILE_NAME)
+        .map_err(|err| HookError::ExecutionError(format!("Error adding ignore rule: {}", err)))?;
+
+    for source in sources {
+        let ignore_file = source.join(IGNORE_FILE_NAME);
+
+        if !ignore_file.is_file() {
+            continue;
+        }
+
+        if let Some(err) = builder.add(&ignore_file) {
+            return Err(HookError::ExecutionError(format!(
+                "Error reading {}: {}",
+                ignore_file.display(),
+                err
+            )));
+        }
+    }
+
+    for pattern in patterns {
+        builder.add_line(None, pattern).map_err(|err| {
+            HookError::ExecutionError(format!("Error adding ignore rule: {}", err))
+        })?;
+    }
+
+    builder
+        .build()
+        .map_err(|err| HookError::ExecutionError(format!("Error building ignore rules: {}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn test_ignore_patterns() {
+        let ignore = build_ignore(&[], &[".git".to_string(), "*.swp".to_string()]).unwrap();
+
+        assert!(ignore.matched(Path::new(".git"), true).is_ignore());
+        assert!(ignore
+            .matched(Path::new(".config/nvim/.init.lua.swp"), false)
+            .is_ignore());
+        assert!(ignore.matched(Path::new(".hookignore"), false).is_ignore());
+        assert!(!ignore
+            .matched(Path::new(".config/nvim/init.lua"), false)
+            .is_ignore());
+    }
+}
@@ -1,6 +1,20 @@
+mod alternates;
 mod args;
+mod batch;
+mod condition;
 mod error;
+mod escalate;
+mod export;
+mod facts;
+mod guard;
+mod ignores;
+mod manifest;
+mod permissions;
+mod plan;
 mod program;
+mod scripts;
+mod state;
+mod template;
 mod utils;
 
 fn main() {
@@ -8,5 +22,9 @@ fn main() {
 
     if let Err(err) = result {
         eprintln!("{}", err);
+
+        if !matches!(err, error::HookError::Skipping(_)) {
+            std::process::exit(1);
+        }
     }
 }
@@ -0,0 +1,444 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use crate::{args::LinkType, error::HookError};
+
+/// A TOML file listing the links to create, so a whole machine can be set up in one run.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Gitignore-style patterns skipped by every entry linked per file.
+    #[serde(default)]
+    pub ignore: Vec<String>,
+
+    /// Variables for entries of the `template` type.
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+
+    /// Named groups of tags, selected with --profile.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Vec<String>>,
+
+    /// Packages which entries belong to, declaring the packages they depend on.
+    #[serde(default)]
+    pub packages: BTreeMap<String, Package>,
+
+    #[serde(default, rename = "link")]
+    pub links: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Package {
+    /// Packages which are linked before this one, and with it when it's selected.
+    #[serde(default)]
+    pub requires: Vec<String>,
+
+    /// Commands run before the first entry of the package which changes.
+    #[serde(default)]
+    pub pre: Vec<String>,
+
+    /// Commands run after the entries of the package, if any of them changed.
+    #[serde(default)]
+    pub post: Vec<String>,
+}
+
+impl Manifest {
+    /// Every package, ordered so each comes after the packages it requires.
+    pub fn package_order(&self) -> Result<Vec<String>, HookError> {
+        let mut order = Vec::new();
+        let mut visiting = Vec::new();
+
+        let names = self
+            .packages
+            .keys()
+            .chain(self.links.iter().filter_map(|entry| entry.package.as_ref()))
+            .collect::<BTreeSet<_>>();
+
+        for name in names {
+            self.visit_package(name, &mut visiting, &mut order)?;
+        }
+
+        Ok(order)
+    }
+
+    fn visit_package(
+        &self,
+        name: &str,
+        visiting: &mut Vec<String>,
+        order: &mut Vec<String>,
+    ) -> Result<(), HookError> {
+        if order.iter().any(|ordered| ordered == name) {
+            return Ok(());
+        }
+
+        if let Some(start) = visiting.iter().position(|visited| visited == name) {
+            let mut cycle = visiting[start..].to_vec();
+            cycle.push(name.to_string());
+            return Err(HookError::DependencyCycle(cycle));
+        }
+
+        visiting.push(name.to_string());
+
+        for dependency in self.requires(name) {
+            if !self.is_package(dependency) {
+                return Err(HookError::UnknownPackage(dependency.clone()));
+            }
+
+            self.visit_package(dependency, visiting, order)?;
+        }
+
+        visiting.pop();
+        order.push(name.to_string());
+
+        Ok(())
+    }
+
+    /// The packages together with every package they require, directly or not.
+    pub fn with_dependencies(&self, packages: &[String]) -> Result<BTreeSet<String>, HookError> {
+        let mut selected = BTreeSet::new();
+        let mut pending = packages.to_vec();
+
+        while let Some(package) = pending.pop() {
+            if !self.is_package(&package) {
+                return Err(HookError::UnknownPackage(package));
+            }
+
+            if selected.insert(package.clone()) {
+                pending.extend(self.requires(&package).iter().cloned());
+            }
+        }
+
+        Ok(selected)
+    }
+
+    fn requires(&self, package: &str) -> &[String] {
+        self.packages
+            .get(package)
+            .map(|package| package.requires.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn is_package(&self, name: &str) -> bool {
+        self.packages.contains_key(name)
+            || self
+                .links
+                .iter()
+                .any(|entry| entry.package.as_deref() == Some(name))
+    }
+
+    /// Combines the tags of the profile with the tags passed directly. Every entry is selected
+    /// when neither is given.
+    pub fn tag_selection(
+        &self,
+        profile: Option<&str>,
+        tags: &[String],
+        skip_tags: &[String],
+    ) -> Result<TagSelection, HookError> {
+        let mut selected = match profile {
+            Some(profile) => Some(
+                self.profiles
+                    .get(profile)
+                    .cloned()
+                    .ok_or_else(|| HookError::UnknownProfile(profile.to_string()))?,
+            ),
+            None => None,
+        };
+
+        if !tags.is_empty() {
+            selected
+                .get_or_insert_with(Vec::new)
+                .extend_from_slice(tags);
+        }
+
+        Ok(TagSelection {
+            tags: selected,
+            skip_tags: skip_tags.to_vec(),
+        })
+    }
+}
+
+/// Which manifest entries are linked, by their tags.
+#[derive(Debug, Clone)]
+pub struct TagSelection {
+    tags: Option<Vec<String>>,
+    skip_tags: Vec<String>,
+}
+
+impl TagSelection {
+    /// Whether the entry has one of the selected tags, if any are, and none of the skipped ones.
+    pub fn includes(&self, entry: &ManifestEntry) -> bool {
+        let selected = match &self.tags {
+            Some(tags) => entry.tags.iter().any(|tag| tags.contains(tag)),
+            None => true,
+        };
+
+        selected && !entry.tags.iter().any(|tag| self.skip_tags.contains(tag))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestEntry {
+    /// Relative to the directory containing the manifest.
+    pub source: String,
+    pub destination: String,
+
+    #[serde(default, rename = "type")]
+    pub link_type: LinkType,
+
+    /// A condition on the host facts, the entry is only linked when it's true.
+    pub when: Option<String>,
+
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// The package the entry belongs to.
+    pub package: Option<String>,
+
+    /// The mode the managed files must have, such as `0o600`. It applies to the source, and
+    /// also to the destination for copies and rendered templates.
+    pub mode: Option<u32>,
+
+    /// The user owning the managed files, only enforced when running as root.
+    pub owner: Option<String>,
+
+    /// The group owning the managed files, only enforced when running as root.
+    pub group: Option<String>,
+
+    /// Commands run before linking the entry, only when linking it changes anything.
+    #[serde(default)]
+    pub pre: Vec<String>,
+
+    /// Commands run after linking the entry, only when linking it changed anything.
+    #[serde(default)]
+    pub post: Vec<String>,
+
+    #[serde(default)]
+    pub per_file: bool,
+
+    #[serde(default)]
+    pub fold: bool,
+
+    #[serde(default)]
+    pub relative: bool,
+
+    #[serde(default)]
+    pub ignore: Vec<String>,
+}
+
+impl ManifestEntry {
+    /// Checks the entry doesn't combine options the command line rejects together, such as a
+    /// copy which is folded. `fold` and `relative` are the options passed on the command line.
+    pub fn check_options(&self, fold: bool, relative: bool) -> Result<(), String> {
+        if self.link_type == LinkType::Symlink {
+            return Ok(());
+        }
+
+        let option = match (self.fold || fold, self.relative || relative) {
+            (true, _) => "fold",
+            (_, true) => "relative",
+            _ => return Ok(()),
+        };
+
+        Err(format!(
+            "type = \"{}\" can't be combined with {}",
+            format!("{:?}", self.link_type).to_lowercase(),
+            option
+        ))
+    }
+}
+
+pub fn load_manifest(path: &Path) -> Result<Manifest, HookError> {
+    let invalid = |message: String| HookError::InvalidManifest {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    let content = std::fs::read_to_string(path).map_err(|err| invalid(err.to_string()))?;
+    let manifest: Manifest = toml::from_str(&content).map_err(|err| invalid(err.to_string()))?;
+
+    for entry in &manifest.links {
+        entry.check_options(false, false).map_err(|message| {
+            invalid(format!(
+                "{} -> {}: {}",
+                entry.destination, entry.source, message
+            ))
+        })?;
+    }
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            ignore = [".git"]
+
+            [vars]
+            email = "me@example.com"
+
+            [[link]]
+            source = "git/config"
+            destination = "~/.gitconfig"
+            type = "template"
+            when = "os == 'linux'"
+            tags = ["git"]
+            mode = 0o600
+
+            [[link]]
+            source = "nvim"
+            destination = "~/.config/nvim"
+            per_file = true
+            tags = ["editor", "gui"]
+
+            [profiles]
+            server = ["git"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.ignore, [".git"]);
+        assert_eq!(manifest.vars["email"], "me@example.com");
+        assert_eq!(manifest.links.len(), 2);
+        assert_eq!(manifest.links[0].link_type, LinkType::Template);
+        assert_eq!(manifest.links[0].mode, Some(0o600));
+        assert_eq!(manifest.links[1].link_type, LinkType::Symlink);
+        assert!(manifest.links[1].per_file);
+    }
+
+    #[test]
+    fn test_check_options() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            [[link]]
+            source = "nvim"
+            destination = "~/.config/nvim"
+            type = "copy"
+            fold = true
+
+            [[link]]
+            source = "bashrc"
+            destination = "~/.bashrc"
+            type = "hard"
+
+            [[link]]
+            source = "fonts"
+            destination = "~/.local/share/fonts"
+            fold = true
+            relative = true
+            "#,
+        )
+        .unwrap();
+
+        assert!(manifest.links[0].check_options(false, false).is_err());
+        assert!(manifest.links[1].check_options(false, false).is_ok());
+        assert!(manifest.links[1].check_options(false, true).is_err());
+        assert!(manifest.links[2].check_options(true, true).is_ok());
+    }
+
+    #[test]
+    fn test_tag_selection() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            [profiles]
+            server = ["shell", "git"]
+
+            [[link]]
+            source = "bashrc"
+            destination = "~/.bashrc"
+            tags = ["shell"]
+
+            [[link]]
+            source = "nvim"
+            destination = "~/.config/nvim"
+            tags = ["editor", "gui"]
+
+            [[link]]
+            source = "profile"
+            destination = "~/.profile"
+            "#,
+        )
+        .unwrap();
+
+        let included = |selection: TagSelection| {
+            manifest
+                .links
+                .iter()
+                .map(|entry| selection.includes(entry))
+                .collect::<Vec<_>>()
+        };
+
+        let all = manifest.tag_selection(None, &[], &[]).unwrap();
+        assert_eq!(included(all), [true, true, true]);
+
+        let server = manifest.tag_selection(Some("server"), &[], &[]).unwrap();
+        assert_eq!(included(server), [true, false, false]);
+
+        let tags = ["editor".to_string()];
+        let editor = manifest.tag_selection(Some("server"), &tags, &[]).unwrap();
+        assert_eq!(included(editor), [true, true, false]);
+
+        let skip = ["gui".to_string()];
+        let no_gui = manifest.tag_selection(None, &[], &skip).unwrap();
+        assert_eq!(included(no_gui), [true, false, true]);
+
+        assert!(manifest.tag_selection(Some("laptop"), &[], &[]).is_err());
+    }
+
+    #[test]
+    fn test_package_order() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            [packages.nvim]
+            requires = ["fonts", "shell-env"]
+
+            [packages.shell-env]
+            requires = ["fonts"]
+
+            [[link]]
+            source = "fonts"
+            destination = "~/.local/share/fonts"
+            package = "fonts"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            manifest.package_order().unwrap(),
+            ["fonts", "shell-env", "nvim"]
+        );
+        assert_eq!(
+            manifest
+                .with_dependencies(&["shell-env".to_string()])
+                .unwrap(),
+            BTreeSet::from(["fonts".to_string(), "shell-env".to_string()])
+        );
+        assert!(manifest.with_dependencies(&["git".to_string()]).is_err());
+
+        let cyclic: Manifest = toml::from_str(
+            r#"
+            [packages.a]
+            requires = ["b"]
+
+            [packages.b]
+            requires = ["a"]
+            "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            cyclic.package_order(),
+            Err(HookError::DependencyCycle(cycle)) if cycle == ["a", "b", "a"]
+        ));
+    }
+}
@@ -0,0 +1,262 @@
+use std::path::Path;
+
+use crate::error::HookError;
+
+/// The mode and ownership a manifest entry requires for the files it manages.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequiredPermissions {
+    pub mode: Option<u32>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+}
+
+/// How a path differs from the required permissions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PermissionDrift {
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl RequiredPermissions {
+    /// Compares the path to the required permissions. Ownership is only compared when running as
+    /// root, since nobody else can change it.
+    pub fn drift(&self, path: &Path) -> Result<PermissionDrift, HookError> {
+        let mut drift = PermissionDrift::default();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let metadata = path.metadata().map_err(|err| {
+                HookError::ExecutionError(format!("Error reading {}: {}", path.display(), err))
+            })?;
+
+            drift.mode = self.mode.filter(|mode| metadata.mode() & 0o7777 != *mode);
+
+            if is_root() {
+                let uid = self.owner.as_deref().map(user_id).transpose()?;
+                let gid = self.group.as_deref().map(group_id).transpose()?;
+
+                drift.uid = uid.filter(|uid| metadata.uid() != *uid);
+                drift.gid = gid.filter(|gid| metadata.gid() != *gid);
+            }
+        }
+
+        #[cfg(not(unix))]
+        let _ = path;
+
+        Ok(drift)
+    }
+}
+
+impl RequiredPermissions {
+    /// The permissions a path which is only planned to exist will be given once it does.
+    pub fn planned(&self) -> Result<PermissionDrift, HookError> {
+        let mut drift = PermissionDrift {
+            mode: self.mode,
+            ..Default::default()
+        };
+
+        if is_root() {
+            drift.uid = self.owner.as_deref().map(user_id).transpose()?;
+            drift.gid = self.group.as_deref().map(group_id).transpose()?;
+        }
+
+        Ok(drift)
+    }
+}
+
+impl PermissionDrift {
+    pub fn is_empty(&self) -> bool {
+        self == &PermissionDrift::default()
+    }
+}
+
+pub fn is_root() -> bool {
+    #[cfg(unix)]
+    {
+        // SAFETY: geteuid has no preconditions.
+        unsafe { libc::geteuid() == 0 }
+    }
+
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+/// The owner given to files hook moves or creates inside the source tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceOwner {
+    pub uid: u32,
+    pub gid: Option<u32>,
+}
+
+/// The owner to give files hook moves or creates inside the source tree: the one passed with
+/// --owner as `user[:group]`, or the user who ran hook with sudo. Without a group the user's
+/// primary group is used.
+pub fn source_owner(owner: Option<&str>) -> Result<Option<SourceOwner>, HookError> {
+    resolve_source_owner(
+        owner,
+        is_root(),
+        std::env::var("SUDO_UID").ok(),
+        std::env::var("SUDO_GID").ok(),
+    )
+}
+
+fn resolve_source_owner(
+    owner: Option<&str>,
+    root: bool,
+    sudo_uid: Option<String>,
+    sudo_gid: Option<String>,
+) -> Result<Option<SourceOwner>, HookError> {
+    if let Some(owner) = owner {
+        let (user, group) = match owner.split_once(':') {
+            Some((user, group)) => (user, Some(group)),
+            None => (owner, None),
+        };
+
+        let uid = user_id(user)?;
+        let gid = match group {
+            Some(group) => Some(group_id(group)?),
+            None => primary_group(uid),
+        };
+
+        return Ok(Some(SourceOwner { uid, gid }));
+    }
+
+    if !root {
+        return Ok(None);
+    }
+
+    let sudo_id = |id: Option<String>| id.and_then(|id| id.parse().ok());
+
+    Ok(sudo_id(sudo_uid)
+        .filter(|uid| *uid != 0)
+        .map(|uid| SourceOwner {
+            uid,
+            gid: sudo_id(sudo_gid),
+        }))
+}
+
+fn primary_group(uid: u32) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        // SAFETY: the entry is only read before any other call which could overwrite it.
+        let entry = unsafe { libc::getpwuid(uid) };
+
+        if !entry.is_null() {
+            return Some(unsafe { (*entry).pw_gid });
+        }
+    }
+
+    let _ = uid;
+    None
+}
+
+/// Resolves a user name or numeric id.
+pub fn user_id(owner: &str) -> Result<u32, HookError> {
+    if let Ok(uid) = owner.parse() {
+        return Ok(uid);
+    }
+
+    #[cfg(unix)]
+    {
+        let name = std::ffi::CString::new(owner)
+            .map_err(|_| HookError::UnknownOwner(owner.to_string()))?;
+
+        // SAFETY: the name is a valid C string and the entry is only read before any other call.
+        let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+
+        if !entry.is_null() {
+            return Ok(unsafe { (*entry).pw_uid });
+        }
+    }
+
+    Err(HookError::UnknownOwner(owner.to_string()))
+}
+
+/// Resolves a group name or numeric id.
+pub fn group_id(group: &str) -> Result<u32, HookError> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+
+    #[cfg(unix)]
+    {
+        let name = std::ffi::CString::new(group)
+            .map_err(|_| HookError::UnknownOwner(group.to_string()))?;
+
+        // SAFETY: the name is a valid C string and the entry is only read before any other call.
+        let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+
+        if !entry.is_null() {
+            return Ok(unsafe { (*entry).gr_gid });
+        }
+    }
+
+    Err(HookError::UnknownOwner(group.to_string()))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_drift() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("hook-mode-drift-{}", std::process::id()));
+        std::fs::write(&path, "secret").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let required = RequiredPermissions {
+            mode: Some(0o600),
+            ..Default::default()
+        };
+
+        assert_eq!(required.drift(&path).unwrap().mode, Some(0o600));
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(required.drift(&path).unwrap().is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_source_owner() {
+        let owner = |uid, gid| Some(SourceOwner { uid, gid });
+        let id = |id: &str| Some(id.to_string());
+
+        assert_eq!(
+            resolve_source_owner(Some("1000:1001"), false, None, None).unwrap(),
+            owner(1000, Some(1001))
+        );
+        assert_eq!(
+            resolve_source_owner(Some("root:0"), false, id("1000"), None).unwrap(),
+            owner(0, Some(0))
+        );
+        assert!(matches!(
+            resolve_source_owner(Some("no-such-user-for-hook"), true, None, None),
+            Err(HookError::UnknownOwner(name)) if name == "no-such-user-for-hook"
+        ));
+        assert!(matches!(
+            resolve_source_owner(Some("root:no-such-group-for-hook"), true, None, None),
+            Err(HookError::UnknownOwner(_))
+        ));
+
+        assert_eq!(
+            resolve_source_owner(None, true, id("1000"), id("1001")).unwrap(),
+            owner(1000, Some(1001))
+        );
+        assert_eq!(
+            resolve_source_owner(None, true, id("0"), id("0")).unwrap(),
+            None
+        );
+        assert_eq!(
+            resolve_source_owner(None, false, id("1000"), id("1001")).unwrap(),
+            None
+        );
+    }
+}
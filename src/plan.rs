@@ -0,0 +1,138 @@
+use std::{cell::RefCell, fmt::Display, path::PathBuf, rc::Rc};
+
+/// A filesystem operation hook would perform.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    CreateDirectory(PathBuf),
+    RemoveFile(PathBuf),
+    RemoveDirectory(PathBuf),
+    MoveFile {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    MoveDirectory {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// `target` is written as it will appear in the symlink, while `source` is where the real
+    /// files are found on this machine.
+    Symlink {
+        source: PathBuf,
+        target: PathBuf,
+        destination: PathBuf,
+        directory: bool,
+    },
+    HardLink {
+        source: PathBuf,
+        destination: PathBuf,
+    },
+    Copy {
+        source: PathBuf,
+        destination: PathBuf,
+        reflink: bool,
+    },
+    Render {
+        source: PathBuf,
+        destination: PathBuf,
+        content: String,
+    },
+    SetMode {
+        path: PathBuf,
+        mode: u32,
+    },
+    SetOwner {
+        path: PathBuf,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    },
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::CreateDirectory(path) => write!(f, "Create directory: {}", path.display()),
+            Step::RemoveFile(path) => write!(f, "Remove file: {}", path.display()),
+            Step::RemoveDirectory(path) => write!(f, "Remove directory: {}", path.display()),
+            Step::MoveFile { from, to } => {
+                write!(f, "Move file: {} to {}", from.display(), to.display())
+            }
+            Step::MoveDirectory { from, to } => {
+                write!(f, "Move directory: {} to {}", from.display(), to.display())
+            }
+            Step::Symlink {
+                target,
+                destination,
+                ..
+            } => write!(
+                f,
+                "Create symlink: {} -> {}",
+                destination.display(),
+                target.display()
+            ),
+            Step::HardLink {
+                source,
+                destination,
+            } => write!(
+                f,
+                "Create hard link: {} => {}",
+                destination.display(),
+                source.display()
+            ),
+            Step::Copy {
+                source,
+                destination,
+                reflink: false,
+            } => write!(
+                f,
+                "Copy file: {} to {}",
+                source.display(),
+                destination.display()
+            ),
+            Step::Copy {
+                source,
+                destination,
+                reflink: true,
+            } => write!(
+                f,
+                "Clone file: {} to {}",
+                source.display(),
+                destination.display()
+            ),
+            Step::Render {
+                source,
+                destination,
+                ..
+            } => write!(
+                f,
+                "Render template: {} to {}",
+                source.display(),
+                destination.display()
+            ),
+            Step::SetMode { path, mode } => {
+                write!(f, "Set mode: {} to {:04o}", path.display(), mode)
+            }
+            Step::SetOwner { path, uid, gid } => write!(
+                f,
+                "Set owner: {} to {}:{}",
+                path.display(),
+                uid.map_or_else(|| "-".to_string(), |uid| uid.to_string()),
+                gid.map_or_else(|| "-".to_string(), |gid| gid.to_string())
+            ),
+        }
+    }
+}
+
+/// Collects the steps of an operation instead of performing them. Clones share the same steps,
+/// so it can be passed along with the arguments.
+#[derive(Debug, Clone, Default)]
+pub struct Plan(Rc<RefCell<Vec<Step>>>);
+
+impl Plan {
+    pub fn record(&self, step: Step) {
+        self.0.borrow_mut().push(step);
+    }
+
+    pub fn steps(&self) -> Vec<Step> {
+        self.0.borrow().clone()
+    }
+}
@@ -1,93 +1,1825 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeSet,
+    ffi::{OsStr, OsString},
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
 
 use clap::Parser;
+use ignore::gitignore::Gitignore;
 use symlink::{symlink_dir, symlink_file};
 
-use crate::args::HookArgs;
+use crate::alternates::{alternate_base, select_alternate_paths, select_alternates};
+use crate::args::{
+    Cli, Command, ExportArgs, ExportFormat, HookArgs, LinkType, StatusArgs, SyncArgs,
+};
+use crate::batch::{parse_link_pairs, BatchSummary};
+use crate::condition::evaluate;
 use crate::error::HookError;
-use crate::utils::get_path;
+use crate::escalate::{needs_privileges, run_escalated};
+use crate::export::{write_script, write_tar};
+use crate::guard::{
+    check_not_protected, check_paths_not_nested, check_same_device, measure_removal,
+};
+use crate::ignores::build_ignore;
+use crate::manifest::{load_manifest, Manifest, ManifestEntry, TagSelection};
+use crate::permissions::{is_root, source_owner, RequiredPermissions, SourceOwner};
+use crate::plan::{Plan, Step};
+use crate::scripts::run_commands;
+use crate::state::{
+    forget_copy, forget_created_directory, hash_file, is_unchanged_copy, load_copy_records,
+    load_created_directories, load_folds, record_copy, record_created_directory, record_fold,
+    save_copy_records, CopyRecord,
+};
+use crate::template::render_file;
+use crate::utils::{get_path, get_path_in_root, is_same_file, reflink_or_copy, strip_root};
 
 pub fn run() -> Result<(), HookError> {
-    let args = HookArgs::parse();
+    let cli = Cli::parse();
+
+    match cli.command {
+        Some(Command::Export(export_args)) => export(export_args),
+        Some(Command::Status(status_args)) => status(status_args),
+        Some(Command::Sync(sync_args)) => sync(sync_args),
+        Some(Command::Unlink(unlink_args)) => create_symlinks(HookArgs {
+            unlink: true,
+            ..unlink_args.args
+        }),
+        None => create_symlinks(cli.args),
+    }
+}
+
+fn export(export_args: ExportArgs) -> Result<(), HookError> {
+    let plan = Plan::default();
+
+    let args = HookArgs {
+        plan: Some(plan.clone()),
+        ..export_args.args
+    };
+
+    let root = get_root(&args)?;
+    create_symlinks(args)?;
+
+    let output: Box<dyn Write> = match &export_args.output {
+        Some(output) => {
+            let output = get_full_path(output, "output", None)?;
+
+            Box::new(File::create(output).map_err(|err| {
+                HookError::ExecutionError(format!("Error creating output file: {}", err))
+            })?)
+        }
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    match export_args.format {
+        ExportFormat::Tar => write_tar(
+            &plan.steps(),
+            root.as_deref(),
+            export_args.include_sources,
+            output,
+        ),
+        ExportFormat::Sh => write_script(&plan.steps(), root.as_deref(), output),
+    }
+}
+
+/// Plans the links without creating them and prints the operations which are still needed.
+fn status(status_args: StatusArgs) -> Result<(), HookError> {
+    let plan = Plan::default();
+
+    let args = HookArgs {
+        plan: Some(plan.clone()),
+        quiet: true,
+        verbose: false,
+        interactive: false,
+        ..status_args.args
+    };
+
+    match create_symlinks(args) {
+        Err(HookError::Skipping(_)) => {}
+        result => result?,
+    }
+
+    let steps = plan.steps();
+
+    for step in &steps {
+        println!("{}", step);
+    }
+
+    if !steps.is_empty() {
+        return Err(HookError::Pending(steps.len()));
+    }
+
+    println!("Up to date.");
+    Ok(())
+}
+
+/// Propagates changes between every recorded copy and its source. A side changed since the last
+/// sync is copied over the other, while copies changed on both sides are reported as conflicts.
+fn sync(sync_args: SyncArgs) -> Result<(), HookError> {
+    let mut records = load_copy_records()?;
+    let mut conflicts = 0;
+
+    // Rendered templates are only ever updated by linking again.
+    for record in records
+        .iter_mut()
+        .filter(|record| record.link_type != LinkType::Template)
+    {
+        let source_hash = hash_file(&record.source).ok();
+        let destination_hash = hash_file(&record.destination).ok();
+
+        let (Some(source_hash), Some(destination_hash)) = (source_hash, destination_hash) else {
+            eprintln!(
+                "Missing: {} or {} no longer exists",
+                record.source.display(),
+                record.destination.display()
+            );
+
+            conflicts += 1;
+            continue;
+        };
+
+        let (from, to) = match (source_hash == record.hash, destination_hash == record.hash) {
+            (true, true) => continue,
+            (false, true) => (&record.source, &record.destination),
+            (true, false) => (&record.destination, &record.source),
+            (false, false) if source_hash == destination_hash => {
+                record.hash = source_hash;
+                continue;
+            }
+            (false, false) => {
+                eprintln!(
+                    "Conflict: {} and {} were both changed since the last sync",
+                    record.source.display(),
+                    record.destination.display()
+                );
+
+                conflicts += 1;
+                continue;
+            }
+        };
+
+        if !sync_args.quiet {
+            eprintln!("Copying file: {} to {}", from.display(), to.display());
+        }
+
+        if sync_args.dry_run {
+            continue;
+        }
+
+        copy_file(from, to, record.link_type == LinkType::Reflink)?;
+
+        record.hash = hash_file(to)?;
+    }
+
+    if !sync_args.dry_run {
+        save_copy_records(&records)?;
+    }
+
+    if conflicts > 0 {
+        return Err(HookError::SyncConflicts(conflicts));
+    }
+
+    Ok(())
+}
+
+fn create_symlinks(mut args: HookArgs) -> Result<(), HookError> {
+    args.alternates = Some(args.alternate_context());
+
+    if args.source_owner.is_none() {
+        args.source_owner = source_owner(args.owner.as_deref())?;
+    }
 
     if args.verbose {
         eprintln!("Args: {:#?}", args);
     }
 
-    let source = get_path(&args.source.replace(r"\\", r"/").replace(r"\", "/"))
-        .map_err(|err| HookError::ExecutionError(format!("Error getting source path: {}", err)))?;
+    if args.from_stdin {
+        return create_symlinks_from_stdin(args);
+    }
+
+    if let Some(manifest) = args.manifest.clone() {
+        return create_symlinks_from_manifest(&manifest, args);
+    }
+
+    let root = get_root(&args)?;
+
+    let mut sources = Vec::new();
+
+    for source in &args.source {
+        sources.extend(expand_glob(get_full_path(
+            source,
+            "source",
+            root.as_deref(),
+        )?)?);
+    }
+
+    if let Some(target_directory) = &args.target_directory {
+        let target_directory =
+            get_full_path(target_directory, "target directory", root.as_deref())?;
+
+        if args.verbose {
+            for source in &sources {
+                eprintln!("Source: {}", source.display());
+            }
+
+            eprintln!("Target directory: {}", target_directory.display());
+        }
+
+        return create_symlinks_in_directory(sources, &target_directory, args);
+    }
+
+    let Some(destination) = &args.destination else {
+        return Err(HookError::ExecutionError(
+            "Either a destination or a target directory is required.".to_string(),
+        ));
+    };
+
+    let destination = get_full_path(destination, "destination", root.as_deref())?;
+
+    if args.verbose {
+        for source in &sources {
+            eprintln!("Source: {}", source.display());
+        }
+
+        eprintln!("Destination: {}", destination.display());
+
+        if args.relative {
+            for source in &sources {
+                eprintln!(
+                    "Relative destination: {}",
+                    pathdiff::diff_paths(source, &destination)
+                        .unwrap_or_else(|| PathBuf::from("N/A"))
+                        .display()
+                );
+            }
+        }
+    }
+
+    if sources.len() > 1 {
+        if !args.per_file && !args.fold {
+            return Err(HookError::ExecutionError(
+                "Multiple sources can only be linked with --per-file or --fold.".to_string(),
+            ));
+        }
+
+        let ignore = build_ignore(&sources, &args.ignore)?;
+        return create_symlink_packages(&sources, &destination, &ignore, args);
+    }
+
+    create_symlink_pair(sources.remove(0), destination, args)
+}
+
+fn create_symlink_pair(
+    source: PathBuf,
+    destination: PathBuf,
+    args: HookArgs,
+) -> Result<(), HookError> {
+    let source = resolve_alternate(source, &args)?;
+
+    let destination = match source.file_name() {
+        Some(name) if args.into_directory && source.is_file() && destination.is_dir() => {
+            destination.join(alternate_base(name).map(OsStr::new).unwrap_or(name))
+        }
+        _ => destination,
+    };
+
+    // Only the entries inside the source are linked when linking per file or folding.
+    let links_contents = (args.per_file || args.fold) && source.is_dir();
+
+    if source.file_name() != destination.file_name() && !args.rename && !links_contents {
+        return handle_different_base_names(source, destination, args);
+    }
+
+    check_valid_paths_and_create_symlink(source, destination, args)
+}
+
+fn create_symlinks_from_stdin(args: HookArgs) -> Result<(), HookError> {
+    let mut input = String::new();
+
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|err| HookError::ExecutionError(format!("Error reading input: {}", err)))?;
+
+    let separator = if args.null { '\0' } else { '\n' };
+    let pairs = parse_link_pairs(&input, separator)?;
+    let root = get_root(&args)?;
+
+    let mut summary = BatchSummary::default();
+
+    for (source, destination) in &pairs {
+        let result = get_full_path(source, "source", root.as_deref()).and_then(|source| {
+            let destination = get_full_path(destination, "destination", root.as_deref())?;
+            create_symlink_pair(source, destination, args.clone())
+        });
+
+        summary.record(
+            result,
+            format_args!("{} -> {}", destination, source),
+            args.quiet,
+        );
+    }
+
+    summary.finish(if args.unlink { "Unlinked" } else { "Linked" }, args.quiet)
+}
+
+/// Links every entry of the manifest whose `when` condition holds, with sources relative to the
+/// directory containing the manifest.
+fn create_symlinks_from_manifest(manifest_path: &str, args: HookArgs) -> Result<(), HookError> {
+    let manifest_path = get_full_path(manifest_path, "manifest", None)?;
+    let manifest = load_manifest(&manifest_path)?;
+    let base = manifest_path.parent().unwrap_or(Path::new(""));
+    let context = args.alternate_context();
+    let facts = context.facts();
+    let selection = manifest.tag_selection(args.profile.as_deref(), &args.tags, &args.skip_tags)?;
+    let order = manifest.package_order()?;
+
+    // Unlinking a package leaves the packages it requires in place.
+    let packages = match (args.package.is_empty(), args.unlink) {
+        (true, _) => None,
+        (false, true) => {
+            manifest.with_dependencies(&args.package)?;
+            Some(args.package.iter().cloned().collect())
+        }
+        (false, false) => Some(manifest.with_dependencies(&args.package)?),
+    };
+
+    // Entries without a package come first, then every package after those it requires.
+    let mut entries = manifest
+        .links
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| args.entries.is_empty() || args.entries.contains(index))
+        .filter(|(_, entry)| match (&packages, &entry.package) {
+            (None, _) => true,
+            (Some(packages), Some(package)) => packages.contains(package),
+            (Some(_), None) => false,
+        })
+        .collect::<Vec<_>>();
+
+    entries.sort_by_key(|(_, entry)| {
+        entry
+            .package
+            .as_ref()
+            .and_then(|package| order.iter().position(|ordered| ordered == package))
+            .map_or(0, |position| position + 1)
+    });
+
+    // Links are removed in reverse, so packages go before the packages they require.
+    if args.unlink {
+        entries.reverse();
+
+        if let Some(packages) = &packages {
+            warn_about_dependents(&manifest, packages, &selection);
+        }
+    }
+
+    // Variables given on the command line take priority over the manifest.
+    let mut args = args;
+    args.manifest = None;
+    args.vars = manifest.vars.clone().into_iter().chain(args.vars).collect();
+
+    let root = get_root(&args)?;
+    let mut summary = BatchSummary::default();
+
+    // A run through --escalate links the entries its parent selected and checked the conditions
+    // of, which can differ as root.
+    let preselected = !args.entries.is_empty();
+
+    // The entries which are selected and whose condition holds on this machine.
+    let mut selected = Vec::new();
+
+    for (index, entry) in entries {
+        let link = format!("{} -> {}", entry.destination, entry.source);
+
+        if !preselected && !selection.includes(entry) {
+            if args.verbose {
+                eprintln!("Not selected: {}", link);
+            }
+
+            continue;
+        }
+
+        if let Some(condition) = entry.when.as_ref().filter(|_| !preselected) {
+            match evaluate(condition, facts) {
+                Ok(true) => {}
+                Ok(false) => {
+                    if args.verbose {
+                        eprintln!("Condition not met: {} | {}", link, condition);
+                    }
+
+                    continue;
+                }
+                Err(message) => {
+                    let err = HookError::InvalidCondition {
+                        condition: condition.clone(),
+                        message,
+                    };
+
+                    summary.record(Err(err), &link, args.quiet);
+                    continue;
+                }
+            }
+        }
+
+        // Options passed on the command line apply to every entry, so they're checked here.
+        if let Err(message) = entry.check_options(args.fold, args.relative) {
+            let err = HookError::InvalidManifest {
+                path: manifest_path.clone(),
+                message,
+            };
+
+            summary.record(Err(err), &link, args.quiet);
+            continue;
+        }
+
+        selected.push((index, entry));
+    }
+
+    let escalated = escalated_entries(&selected, &args, root.as_deref());
+
+    // The package whose pre commands ran, so its post commands run once its entries are done.
+    let mut started_package: Option<&String> = None;
+
+    // Entries waiting to be linked through --escalate, which happens before the next entry is
+    // linked here so packages stay in order.
+    let mut batch = Vec::new();
+
+    for (index, entry) in selected {
+        let link = format!("{} -> {}", entry.destination, entry.source);
+
+        if let Some(package) =
+            started_package.filter(|&started| entry.package.as_ref() != Some(started))
+        {
+            if let Err(err) = run_package_commands(&manifest, package, true, base, &args) {
+                summary.record(Err(err), format_args!("package {}", package), args.quiet);
+            }
+
+            started_package = None;
+        }
+
+        if escalated.contains(&index) {
+            batch.push(index);
+            continue;
+        }
+
+        run_escalated_batch(&mut batch, &manifest_path, &args, &mut summary);
+
+        let source = if entry.source.starts_with('~') || Path::new(&entry.source).is_absolute() {
+            entry.source.clone()
+        } else {
+            base.join(&entry.source).to_string_lossy().into_owned()
+        };
+
+        let mut entry_args = args.clone();
+        entry_args.source = vec![source];
+        entry_args.destination = Some(entry.destination.clone());
+        entry_args.rename = true;
+        entry_args.set_link_type(entry.link_type);
+        entry_args.per_file |= entry.per_file;
+        entry_args.fold |= entry.fold;
+        entry_args.relative |= entry.relative;
+        entry_args.permissions = Some(RequiredPermissions {
+            mode: entry.mode,
+            owner: entry.owner.clone(),
+            group: entry.group.clone(),
+        })
+        .filter(|permissions| permissions != &RequiredPermissions::default());
+        entry_args.ignore = [&manifest.ignore, &entry.ignore, &args.ignore]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+
+        if (entry.owner.is_some() || entry.group.is_some()) && !is_root() && !args.quiet {
+            eprintln!(
+                "Warning: the owner and group of {} are only enforced when running as root",
+                link
+            );
+        }
+
+        let package = entry
+            .package
+            .as_ref()
+            .and_then(|package| manifest.packages.get(package));
+
+        let has_commands = !entry.pre.is_empty()
+            || !entry.post.is_empty()
+            || package.is_some_and(|package| !package.pre.is_empty() || !package.post.is_empty());
+
+        // Commands only run when the entry changes and never while only planning.
+        let steps = if has_commands && args.plan.is_none() {
+            match planned_steps(&entry_args) {
+                Ok(steps) => steps,
+                Err(err) => {
+                    summary.record(Err(err), link, args.quiet);
+                    continue;
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        if steps.is_empty() {
+            summary.record(create_symlinks(entry_args), link, args.quiet);
+            continue;
+        }
+
+        if let (None, Some(package)) = (started_package, &entry.package) {
+            let result = run_package_commands(&manifest, package, false, base, &args);
+
+            if result.is_err() {
+                summary.record(result, link, args.quiet);
+                continue;
+            }
+
+            started_package = Some(package);
+        }
+
+        let destination = get_path(&entry.destination)
+            .map(|destination| destination.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| entry.destination.clone());
+
+        let env = [
+            ("HOOK_SOURCE", entry_args.source[0].clone()),
+            ("HOOK_DESTINATION", destination),
+            ("HOOK_ACTION", entry_action(&args, &steps).to_string()),
+        ];
+
+        let result = run_commands(&entry.pre, base, &env, &args)
+            .and_then(|()| create_symlinks(entry_args))
+            .and_then(|()| run_commands(&entry.post, base, &env, &args));
+
+        summary.record(result, link, args.quiet);
+    }
+
+    if let Some(package) = started_package {
+        if let Err(err) = run_package_commands(&manifest, package, true, base, &args) {
+            summary.record(Err(err), format_args!("package {}", package), args.quiet);
+        }
+    }
+
+    run_escalated_batch(&mut batch, &manifest_path, &args, &mut summary);
+
+    summary.finish(if args.unlink { "Unlinked" } else { "Linked" }, args.quiet)
+}
+
+/// The manifest entries to link through --escalate: those whose destination only root can write
+/// to, together with the rest of their packages so every package is linked by a single run.
+fn escalated_entries(
+    entries: &[(usize, &ManifestEntry)],
+    args: &HookArgs,
+    root: Option<&Path>,
+) -> BTreeSet<usize> {
+    if args.escalate.is_none() || args.plan.is_some() || args.dry_run {
+        return BTreeSet::new();
+    }
+
+    let privileged = entries
+        .iter()
+        .filter(|(_, entry)| {
+            let destination = get_full_path(&entry.destination, "destination", root);
+            destination.is_ok_and(|destination| needs_privileges(&destination))
+        })
+        .collect::<Vec<_>>();
+
+    if args.verbose {
+        for (_, entry) in &privileged {
+            eprintln!(
+                "Needs privileges: {} -> {}",
+                entry.destination, entry.source
+            );
+        }
+    }
+
+    let packages = privileged
+        .iter()
+        .filter_map(|(_, entry)| entry.package.as_ref())
+        .collect::<BTreeSet<_>>();
+
+    entries
+        .iter()
+        .filter(|(index, entry)| {
+            privileged.iter().any(|(privileged, _)| privileged == index)
+                || entry
+                    .package
+                    .as_ref()
+                    .is_some_and(|package| packages.contains(package))
+        })
+        .map(|(index, _)| *index)
+        .collect()
+}
+
+/// Links the entries waiting for --escalate. The escalated run prints its own summary, so only
+/// its failure is recorded here.
+fn run_escalated_batch(
+    batch: &mut Vec<usize>,
+    manifest_path: &Path,
+    args: &HookArgs,
+    summary: &mut BatchSummary,
+) {
+    let Some(escalate) = args.escalate.as_deref().filter(|_| !batch.is_empty()) else {
+        return;
+    };
+
+    if let Err(err) = run_escalated(escalate, manifest_path, batch, args) {
+        summary.record(Err(err), "escalated links", args.quiet);
+    }
+
+    batch.clear();
+}
+
+/// Runs the pre or post commands of the package, with `HOOK_PACKAGE` naming it.
+fn run_package_commands(
+    manifest: &Manifest,
+    name: &str,
+    post: bool,
+    base: &Path,
+    args: &HookArgs,
+) -> Result<(), HookError> {
+    let Some(package) = manifest.packages.get(name) else {
+        return Ok(());
+    };
+
+    let env = [
+        ("HOOK_PACKAGE", name.to_string()),
+        ("HOOK_ACTION", hook_action(args).to_string()),
+    ];
+
+    let commands = if post { &package.post } else { &package.pre };
+    run_commands(commands, base, &env, args)
+}
+
+fn hook_action(args: &HookArgs) -> &'static str {
+    if args.unlink {
+        "unlink"
+    } else {
+        "link"
+    }
+}
+
+/// What linking the entry does, from its planned steps: `create` the link, `replace` what's at the
+/// destination, `adopt` the destination into the source, `update` the permissions, or `unlink`.
+fn entry_action(args: &HookArgs, steps: &[Step]) -> &'static str {
+    let any = |matches: fn(&Step) -> bool| steps.iter().any(matches);
+
+    if args.unlink {
+        "unlink"
+    } else if any(|step| matches!(step, Step::MoveFile { .. } | Step::MoveDirectory { .. })) {
+        "adopt"
+    } else if any(|step| matches!(step, Step::RemoveFile(_) | Step::RemoveDirectory(_))) {
+        "replace"
+    } else if any(|step| {
+        matches!(
+            step,
+            Step::Symlink { .. } | Step::HardLink { .. } | Step::Copy { .. } | Step::Render { .. }
+        )
+    }) {
+        "create"
+    } else {
+        "update"
+    }
+}
+
+/// The steps linking with the arguments would take, found by planning it first. Conflicts which
+/// --interactive would ask about are planned as if they were resolved by replacing.
+fn planned_steps(args: &HookArgs) -> Result<Vec<Step>, HookError> {
+    let plan = Plan::default();
+
+    let args = HookArgs {
+        plan: Some(plan.clone()),
+        quiet: true,
+        verbose: false,
+        force: args.force || args.interactive,
+        interactive: false,
+        ..args.clone()
+    };
+
+    match create_symlinks(args) {
+        Ok(()) | Err(HookError::Skipping(_)) => Ok(plan.steps()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Warns about the selected packages which require one of the packages being unlinked.
+fn warn_about_dependents(
+    manifest: &Manifest,
+    unlinked: &BTreeSet<String>,
+    selection: &TagSelection,
+) {
+    let dependents = manifest
+        .links
+        .iter()
+        .filter(|entry| selection.includes(entry))
+        .filter_map(|entry| entry.package.clone())
+        .filter(|package| !unlinked.contains(package))
+        .collect::<BTreeSet<_>>();
+
+    for dependent in dependents {
+        let Ok(dependencies) = manifest.with_dependencies(std::slice::from_ref(&dependent)) else {
+            continue;
+        };
+
+        for package in unlinked.intersection(&dependencies) {
+            eprintln!(
+                "Warning: {} depends on {}, which is being unlinked",
+                dependent, package
+            );
+        }
+    }
+}
+
+fn get_full_path(path: &str, name: &str, root: Option<&Path>) -> Result<PathBuf, HookError> {
+    let path = path.replace(r"\\", r"/").replace(r"\", "/");
+
+    match root {
+        Some(root) => get_path_in_root(&path, root),
+        None => get_path(&path),
+    }
+    .map_err(|err| HookError::ExecutionError(format!("Error getting {} path: {}", name, err)))
+}
+
+fn get_root(args: &HookArgs) -> Result<Option<PathBuf>, HookError> {
+    args.root
+        .as_deref()
+        .map(|root| get_full_path(root, "root", None))
+        .transpose()
+}
+
+/// Expands a glob pattern into the paths matching it. Paths which exist or don't look like a
+/// pattern are returned as they are.
+fn expand_glob(path: PathBuf) -> Result<Vec<PathBuf>, HookError> {
+    let pattern = path.to_string_lossy();
+
+    if path.exists() || !pattern.contains(['*', '?', '[']) {
+        return Ok(vec![path]);
+    }
+
+    let paths = glob::glob(&pattern)
+        .map_err(|err| HookError::ExecutionError(format!("Invalid glob pattern: {}", err)))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| HookError::ExecutionError(format!("Error expanding glob: {}", err)))?;
+
+    if paths.is_empty() {
+        return Err(HookError::ExecutionError(format!(
+            "No paths match the pattern: {}",
+            pattern
+        )));
+    }
+
+    Ok(paths)
+}
+
+fn create_symlinks_in_directory(
+    sources: Vec<PathBuf>,
+    target_directory: &Path,
+    args: HookArgs,
+) -> Result<(), HookError> {
+    if !target_directory.is_dir() {
+        return Err(HookError::ExecutionError(format!(
+            "The target directory must be an existing directory: {}",
+            target_directory.display()
+        )));
+    }
+
+    if let Some(source) = sources.iter().find(|source| source.file_name().is_none()) {
+        return Err(HookError::ExecutionError(format!(
+            "The source path has no base name: {}",
+            source.display()
+        )));
+    }
+
+    let context = args.alternate_context();
+
+    for (source, name) in select_alternate_paths(sources, &context) {
+        let destination = target_directory.join(name);
+
+        match check_valid_paths_and_create_symlink(source, destination, args.clone()) {
+            Err(HookError::Skipping(message)) => {
+                if !args.quiet {
+                    eprintln!("Skipping: {}", message);
+                }
+            }
+            result => result?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces a source which doesn't exist with the best matching alternate for this machine, such
+/// as `.gitconfig##hostname.work` for `.gitconfig`.
+fn resolve_alternate(source: PathBuf, args: &HookArgs) -> Result<PathBuf, HookError> {
+    let (Some(parent), Some(name)) = (source.parent(), source.file_name()) else {
+        return Ok(source);
+    };
+
+    if source.symlink_metadata().is_ok() || !parent.is_dir() {
+        return Ok(source);
+    }
+
+    let alternates = read_dir_sorted(parent)?
+        .into_iter()
+        .filter(|alternate| alternate_base(alternate).map(OsStr::new) == Some(name));
+
+    match select_alternates(alternates, &args.alternate_context()).get(name) {
+        Some(alternate) => {
+            if args.verbose {
+                eprintln!("Using alternate: {}", alternate.to_string_lossy());
+            }
+
+            Ok(parent.join(alternate))
+        }
+        None => Ok(source),
+    }
+}
+
+fn handle_different_base_names(
+    source: PathBuf,
+    destination: PathBuf,
+    args: HookArgs,
+) -> Result<(), HookError> {
+    // An alternate such as `.bashrc##os.Linux` is meant to be linked under its base name.
+    let source_base = source.file_name().and_then(alternate_base).map(OsStr::new);
+
+    if args.force || source_base == destination.file_name() {
+        return check_valid_paths_and_create_symlink(source, destination, args);
+    }
+
+    if !args.interactive {
+        return Err(HookError::DifferentNames);
+    }
+
+    let expected_destination = destination.with_file_name(source.file_name().unwrap());
+
+    eprintln!(
+        "Possible name error: The destination path does not have the same name as the source path."
+    );
+    eprintln!("Source: {}", source.display());
+    eprintln!("Destination: {}", destination.display());
+    eprintln!("Expected destination: {}", expected_destination.display());
+    eprintln!("Which path did you mean to use? (d/e) OR (n) to cancel.");
+
+    let mut input = String::new();
+
+    loop {
+        match std::io::stdin().read_line(&mut input) {
+            Ok(_) => {
+                match input.trim() {
+                    "d" | "D" => {
+                        // Using inputted destination
+                        return check_valid_paths_and_create_symlink(source, destination, args);
+                    }
+                    "e" | "E" => {
+                        // Using expected destination
+                        return check_valid_paths_and_create_symlink(
+                            source,
+                            expected_destination,
+                            args,
+                        );
+                    }
+                    "n" | "N" => {
+                        // Cancel
+                        return Err(HookError::CancelledByUser);
+                    }
+                    _ => {
+                        eprintln!("Invalid input. Please enter 'd', 'e', or 'n'.");
+                        input.clear();
+                    }
+                }
+            }
+            Err(err) => {
+                return Err(HookError::ExecutionError(format!(
+                    "Error reading input: {}",
+                    err
+                )));
+            }
+        }
+    }
+}
+
+fn check_valid_paths_and_create_symlink(
+    source: PathBuf,
+    destination: PathBuf,
+    args: HookArgs,
+) -> Result<(), HookError> {
+    if args.unlink {
+        return remove_links(&source, &destination, args);
+    }
+
+    let source_is_file = source
+        .exists()
+        .then(|| source.metadata().unwrap().is_file());
+
+    let destination_is_file = destination
+        .exists()
+        .then(|| destination.metadata().unwrap().is_file());
+
+    if source_is_file.is_none() && destination_is_file.is_none() {
+        return Err(HookError::PathsDontExist);
+    }
+
+    let per_file = (args.per_file || args.fold) && source.is_dir();
+    check_paths_not_nested(&source, &destination, per_file)?;
+
+    if args.link_type() != LinkType::Symlink && !per_file {
+        if source_is_file == Some(false) || destination_is_file == Some(false) {
+            return Err(HookError::FilesOnly(source));
+        }
+
+        if args.link_type() == LinkType::Hard {
+            check_same_device(&source, &destination)?;
+        }
+    }
+
+    // The source only needs its parents when the destination is moved into it.
+    let source_parents = if source_is_file.is_none() {
+        vec![source.as_path()]
+    } else {
+        vec![]
+    };
+
+    // The files inside a tree linked per file get their permissions as they are linked.
+    let links_tree = (args.per_file || args.fold)
+        && matches!(
+            (source_is_file, destination_is_file),
+            (Some(false), _) | (None, Some(false))
+        );
+
+    let enforce_args = args.clone();
+
+    let result = with_parent_directories(&[&destination], &source_parents, args.clone(), || {
+        match (source_is_file, destination_is_file) {
+            (None, None) => Err(HookError::PathsDontExist),
+            (Some(true), Some(false)) => Err(HookError::ExecutionError(
+                "The destination path must be a file if the source path is a file. Pass --into-directory to link the file inside it.".to_string(),
+            )),
+            (Some(false), Some(true)) => Err(HookError::ExecutionError(
+                "The destination path must be a directory if the source path is a directory."
+                    .to_string(),
+            )),
+            (Some(false), _) | (None, Some(false)) if args.per_file || args.fold => {
+                let ignore = build_ignore(std::slice::from_ref(&source), &args.ignore)?;
+                create_symlink_tree(&source, &destination, &ignore, args)
+            }
+            (Some(true), None) => create_symlink_file(&source, &destination, args),
+            (None, Some(true)) => create_symlink_file(&source, &destination, args),
+            (Some(false), None) => create_symlink_directory(&source, &destination, args),
+            (None, Some(false)) => create_symlink_directory(&source, &destination, args),
+            (Some(true), Some(true)) => create_symlink_file(&source, &destination, args),
+            (Some(false), Some(false)) => create_symlink_directory(&source, &destination, args),
+        }
+    });
+
+    if links_tree {
+        return result;
+    }
+
+    then_enforce_permissions(result, &source, &destination, enforce_args)
+}
+
+/// Enforces the permissions once linking succeeded or had nothing to do.
+fn then_enforce_permissions(
+    result: Result<(), HookError>,
+    source: &Path,
+    destination: &Path,
+    args: HookArgs,
+) -> Result<(), HookError> {
+    match result {
+        Ok(()) | Err(HookError::Skipping(_)) if args.permissions.is_some() => {
+            enforce_permissions(source, destination, args)?;
+            result
+        }
+        result => result,
+    }
+}
+
+/// Gives the source the mode and ownership required by its manifest entry, as well as the
+/// destination when it's a separate file rather than a link. For directories they are given to
+/// each file inside, leaving the directories themselves alone.
+fn enforce_permissions(source: &Path, destination: &Path, args: HookArgs) -> Result<(), HookError> {
+    let Some(required) = args.permissions.clone() else {
+        return Ok(());
+    };
+
+    // A directory which is only planned to be moved into the source is still at the destination.
+    let tree = if is_real_dir(source) {
+        Some(source)
+    } else if !source.exists() && is_real_dir(destination) {
+        Some(destination)
+    } else {
+        None
+    };
+
+    if let Some(tree) = tree {
+        for relative_path in files_in_tree(tree)? {
+            enforce_permissions(
+                &source.join(&relative_path),
+                &destination.join(&relative_path),
+                args.clone(),
+            )?;
+        }
+
+        return Ok(());
+    }
+
+    let mut paths = vec![source];
+
+    if matches!(
+        args.link_type(),
+        LinkType::Copy | LinkType::Reflink | LinkType::Template
+    ) {
+        paths.push(destination);
+    }
+
+    for path in paths {
+        // Paths which are only planned to exist are given every required permission.
+        let drift = if path.exists() {
+            required.drift(path)?
+        } else if args.plan.is_some() || args.dry_run {
+            required.planned()?
+        } else {
+            continue;
+        };
+
+        if drift.is_empty() {
+            continue;
+        }
+
+        if let Some(mode) = drift.mode {
+            set_mode(path, mode, args.clone())?;
+        }
+
+        if drift.uid.is_some() || drift.gid.is_some() {
+            set_owner(path, drift.uid, drift.gid, args.clone())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn create_symlink_file(source: &Path, destination: &Path, args: HookArgs) -> Result<(), HookError> {
+    if args.link_type() == LinkType::Hard && is_same_file(source, destination) {
+        return Err(HookError::Skipping(format!("The destination path is already a hard link to the source path | Source: {} | Destination: {}", source.display(), destination.display())));
+    }
+
+    if matches!(args.link_type(), LinkType::Copy | LinkType::Reflink)
+        && is_same_content(source, destination)
+    {
+        if args.plan.is_none() && !args.dry_run {
+            record_copy(source, destination, args.link_type())?;
+        }
+
+        return Err(HookError::Skipping(format!("The destination path is already a copy of the source path | Source: {} | Destination: {}", source.display(), destination.display())));
+    }
+
+    // A copy changed on one side since the last sync is brought up to date like `hook sync`
+    // does, so status reports it as pending.
+    if matches!(args.link_type(), LinkType::Copy | LinkType::Reflink)
+        && destination.is_file()
+        && !destination.is_symlink()
+    {
+        if let Some(record) = find_copy_record(source, destination)? {
+            match update_copy(&record, args.clone()) {
+                Err(HookError::SyncConflicts(_)) if args.force => {}
+                result => return result,
+            }
+        }
+    }
+
+    if args.link_type() == LinkType::Template && destination.is_file() && !destination.is_symlink()
+    {
+        let content = render_file(source, &args)?;
+
+        if std::fs::read(destination).is_ok_and(|current| current == content.as_bytes()) {
+            if args.plan.is_none() && !args.dry_run {
+                record_copy(source, destination, LinkType::Template)?;
+            }
+
+            return Err(HookError::Skipping(format!("The destination path is already rendered from the source path | Source: {} | Destination: {}", source.display(), destination.display())));
+        }
+
+        // Output rendered by an earlier run and not edited since is stale and can be replaced.
+        if is_unchanged_rendering(source, destination)? {
+            remove_file(destination, args.clone())?;
+            return create_render_op(source, destination, args);
+        }
+    }
+
+    if destination.is_symlink() {
+        handle_symlink_different_target(source, destination, args.clone())?;
+    }
+
+    if args.verbose {
+        eprintln!(
+            "Trying to create symlink file: {} -> {}",
+            destination.display(),
+            source.display(),
+        );
+    }
+
+    match (destination.exists(), source.exists()) {
+        (false, true) => create_file_link_op(source, destination, args.clone()),
+        (true, false) => {
+            move_file(destination, source, args.clone())?;
+            create_file_link_op(source, destination, args.clone())
+        }
+        (true, true) => {
+            if !args.force && !args.interactive {
+                return Err(HookError::FilesAlreadyExists);
+            }
+
+            if args.interactive {
+                eprintln!("The source and destination paths both exist.");
+                eprintln!("Source: {}", source.display());
+                eprintln!("Destination: {}", destination.display());
+                eprintln!("Which do you wish to keep? (s/d) OR (n) to cancel.");
+
+                let mut input = String::new();
+
+                loop {
+                    match std::io::stdin().read_line(&mut input) {
+                        Ok(_) => {
+                            match input.trim() {
+                                "s" | "S" => {
+                                    // Keeping source
+                                    remove_file(destination, args.clone())?;
+                                    break;
+                                }
+                                "d" | "D" => {
+                                    // Keeping destination
+                                    remove_file(source, args.clone())?;
+                                    move_file(destination, source, args.clone())?;
+                                    break;
+                                }
+                                "n" | "N" => {
+                                    // Cancel
+                                    return Err(HookError::CancelledByUser);
+                                }
+                                _ => {
+                                    eprintln!("Invalid input. Please enter 's', 'd', or 'n'.");
+                                    input.clear();
+                                }
+                            }
+                        }
+                        Err(err) => {
+                            return Err(HookError::ExecutionError(format!(
+                                "Error reading input: {}",
+                                err
+                            )));
+                        }
+                    }
+                }
+            } else {
+                // args.force is always true here
+                remove_file(destination, args.clone())?;
+            }
+
+            create_file_link_op(source, destination, args)
+        }
+        (false, false) => {
+            unreachable!(
+                "Check occurs in {}",
+                stringify!(check_valid_paths_and_create_symlink)
+            );
+        }
+    }
+}
+
+fn create_symlink_directory(
+    source: &Path,
+    destination: &Path,
+    args: HookArgs,
+) -> Result<(), HookError> {
+    if destination.is_symlink() {
+        handle_symlink_different_target(source, destination, args.clone())?;
+    }
+
+    if args.verbose {
+        eprintln!(
+            "Trying to create symlink directory: {} -> {}",
+            destination.display(),
+            source.display(),
+        );
+    }
+
+    assert!(source.extension().is_none());
+    assert!(destination.extension().is_none());
+
+    match (destination.exists(), source.exists()) {
+        (false, true) => create_symlink_directory_op(source, destination, args),
+        (true, false) => {
+            move_directory(destination, source, args.clone())?;
+            create_symlink_directory_op(source, destination, args)
+        }
+        (true, true) => {
+            if is_dir_empty(destination) {
+                remove_directory(destination, args.clone())?;
+                return create_symlink_directory(source, destination, args);
+            } else if is_dir_empty(source) {
+                remove_directory(source, args.clone())?;
+                return create_symlink_directory(source, destination, args);
+            }
+
+            if !args.force && !args.interactive {
+                return Err(HookError::FilesAlreadyExists);
+            }
+
+            if args.interactive {
+                eprintln!("The source and destination paths both exist and have files in them.");
+                eprintln!("Source: {}", source.display());
+                eprintln!("Destination: {}", destination.display());
+                eprintln!("Which do you wish to keep? (s/d) OR (n) to cancel.");
+
+                let mut input = String::new();
+                loop {
+                    match std::io::stdin().read_line(&mut input) {
+                        Ok(_) => {
+                            match input.trim() {
+                                "s" | "S" => {
+                                    // Keeping source
+                                    remove_directory(destination, args.clone())?;
+                                    break;
+                                }
+                                "d" | "D" => {
+                                    // Keeping destination
+                                    remove_directory(source, args.clone())?;
+                                    move_directory(destination, source, args.clone())?;
+                                    break;
+                                }
+                                "n" | "N" => {
+                                    // Cancel
+                                    return Err(HookError::CancelledByUser);
+                                }
+                                _ => {
+                                    eprintln!("Invalid input. Please enter 's', 'd', or 'n'.");
+                                    input.clear();
+                                }
+                            }
+                        }
+                        Err(err) => {
+                            eprintln!("Error reading input: {}", err);
+                        }
+                    }
+                }
+            } else {
+                // args.force is always true here
+                remove_directory(destination, args.clone())?;
+            }
+
+            create_symlink_directory_op(source, destination, args)
+        }
+        (false, false) => {
+            create_source_directory(source, args.clone())?;
+            create_symlink_directory_op(source, destination, args)
+        }
+    }
+}
+
+fn create_symlink_tree(
+    source: &Path,
+    destination: &Path,
+    ignore: &Gitignore,
+    args: HookArgs,
+) -> Result<(), HookError> {
+    create_symlink_tree_entry(
+        &[source.to_path_buf()],
+        destination,
+        Path::new(""),
+        ignore,
+        args,
+    )
+}
+
+fn create_symlink_packages(
+    sources: &[PathBuf],
+    destination: &Path,
+    ignore: &Gitignore,
+    args: HookArgs,
+) -> Result<(), HookError> {
+    if let Some(source) = sources.iter().find(|source| !source.is_dir()) {
+        return Err(HookError::ExecutionError(format!(
+            "Every source must be an existing directory when passing multiple sources: {}",
+            source.display()
+        )));
+    }
+
+    if args.unlink {
+        for source in sources {
+            remove_links(source, destination, args.clone())?;
+        }
+
+        return Ok(());
+    }
+
+    for source in sources {
+        check_paths_not_nested(source, destination, true)?;
+    }
+
+    with_parent_directories(&[destination], &[], args.clone(), || {
+        create_symlink_tree_entry(sources, destination, Path::new(""), ignore, args)
+    })
+}
+
+/// `sources` holds the same relative path inside every source providing it, ordered from lowest
+/// to highest priority.
+fn create_symlink_tree_entry(
+    sources: &[PathBuf],
+    destination: &Path,
+    relative_path: &Path,
+    ignore: &Gitignore,
+    args: HookArgs,
+) -> Result<(), HookError> {
+    let source = &sources[sources.len() - 1];
+
+    // When a single source doesn't exist yet, the files are adopted from the destination.
+    let adopting = sources.len() == 1 && !source.exists();
+    let folded_source = (sources.len() == 1).then_some(source.as_path());
+
+    if args.fold {
+        if is_foreign_tree_link(folded_source, destination)? {
+            unfold_directory(destination, args.clone())?;
+        } else if folded_source.is_some()
+            && !adopting
+            && !destination.exists()
+            && !destination.is_symlink()
+            && !contains_ignored(source, relative_path, ignore)?
+        {
+            create_symlink_directory_op(source, destination, args.clone())?;
+
+            if args.plan.is_none() && !args.dry_run {
+                record_fold(destination, source)?;
+            }
+
+            return enforce_permissions(source, destination, args);
+        }
+    }
+
+    if destination.is_symlink() {
+        handle_symlink_different_target(source, destination, args.clone())?;
+    }
+
+    if args.verbose {
+        eprintln!(
+            "Trying to create symlinks for each file: {} -> {}",
+            destination.display(),
+            source.display(),
+        );
+    }
+
+    let mut names = BTreeSet::new();
+
+    // The name every source provides each destination name as, after picking alternates.
+    let mut provided = Vec::new();
+
+    if adopting {
+        names.extend(read_dir_sorted(destination)?);
+        create_source_directory(source, args.clone())?;
+    } else {
+        let context = args.alternate_context();
+
+        for source in sources {
+            let selected = select_alternates(read_dir_sorted(source)?, &context);
+            names.extend(selected.keys().cloned());
+            provided.push(selected);
+        }
+    }
+
+    if !destination.exists() {
+        create_destination_directory(destination, args.clone())?;
+    }
+
+    for name in names {
+        let destination = destination.join(&name);
+        let relative_path = relative_path.join(&name);
+
+        let is_directory = if adopting {
+            is_real_dir(&destination)
+        } else {
+            sources
+                .iter()
+                .zip(&provided)
+                .filter_map(|(source, selected)| Some(source.join(selected.get(&name)?)))
+                .any(|source| is_real_dir(&source))
+        };
+
+        if ignore.matched(&relative_path, is_directory).is_ignore() {
+            if args.verbose {
+                eprintln!("Ignoring: {}", relative_path.display());
+            }
+
+            continue;
+        }
+
+        let result = if adopting {
+            let source = source.join(&name);
+
+            if is_directory {
+                create_symlink_tree_entry(
+                    &[source],
+                    &destination,
+                    &relative_path,
+                    ignore,
+                    args.clone(),
+                )
+            } else {
+                let result = create_symlink_file(&source, &destination, args.clone());
+                then_enforce_permissions(result, &source, &destination, args.clone())
+            }
+        } else {
+            let providers = sources
+                .iter()
+                .zip(&provided)
+                .filter_map(|(source, selected)| Some(source.join(selected.get(&name)?)))
+                .collect::<Vec<_>>();
+
+            create_symlink_tree_provided_entry(
+                &providers,
+                &destination,
+                &relative_path,
+                ignore,
+                args.clone(),
+            )
+        };
+
+        match result {
+            Err(HookError::Skipping(message)) => {
+                if !args.quiet {
+                    eprintln!("Skipping: {}", message);
+                }
+            }
+            result => result?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Links an entry provided by one or more sources. The last provider takes priority and the
+/// others are reported as conflicts, except for directories which are merged.
+fn create_symlink_tree_provided_entry(
+    providers: &[PathBuf],
+    destination: &Path,
+    relative_path: &Path,
+    ignore: &Gitignore,
+    args: HookArgs,
+) -> Result<(), HookError> {
+    let source = &providers[providers.len() - 1];
+
+    let (merged, shadowed) = if is_real_dir(source) {
+        providers
+            .iter()
+            .cloned()
+            .partition::<Vec<_>, _>(|provider| is_real_dir(provider))
+    } else {
+        (
+            vec![source.clone()],
+            providers[..providers.len() - 1].to_vec(),
+        )
+    };
+
+    if !shadowed.is_empty() && !args.quiet {
+        eprintln!(
+            "Conflict: {} is provided by multiple sources, using {}",
+            relative_path.display(),
+            source.display()
+        );
+
+        for provider in &shadowed {
+            eprintln!("Ignoring: {}", provider.display());
+        }
+    }
+
+    if is_real_dir(source) {
+        return create_symlink_tree_entry(&merged, destination, relative_path, ignore, args);
+    }
+
+    // A link to a source with lower priority is replaced without asking, since the conflict has
+    // already been reported.
+    if destination.is_symlink() {
+        let target = destination.canonicalize().ok();
+
+        if shadowed
+            .iter()
+            .any(|provider| provider.canonicalize().ok() == target)
+        {
+            remove_file(destination, args.clone())?;
+        }
+    }
+
+    let result = create_symlink_file(source, destination, args.clone());
+    then_enforce_permissions(result, source, destination, args)
+}
+
+/// Whether the destination is a directory symlink which --fold created for another source, and
+/// still points there. Other symlinks are left to the usual handling.
+fn is_foreign_tree_link(source: Option<&Path>, destination: &Path) -> Result<bool, HookError> {
+    if !destination.is_symlink() {
+        return Ok(false);
+    }
+
+    let Ok(target) = destination.canonicalize() else {
+        return Ok(false);
+    };
+
+    let source = source.and_then(|source| source.canonicalize().ok());
+
+    if source.as_ref() == Some(&target) || !target.is_dir() {
+        return Ok(false);
+    }
+
+    Ok(load_folds()?.iter().any(|(folded, folded_source)| {
+        folded == destination
+            && folded_source
+                .canonicalize()
+                .is_ok_and(|path| path == target)
+    }))
+}
+
+/// Whether anything inside the directory matches the ignore rules, so it can't be linked whole.
+fn contains_ignored(
+    directory: &Path,
+    relative_path: &Path,
+    ignore: &Gitignore,
+) -> Result<bool, HookError> {
+    for name in read_dir_sorted(directory)? {
+        let path = directory.join(&name);
+        let relative_path = relative_path.join(&name);
+        let is_directory = is_real_dir(&path);
+
+        if ignore.matched(&relative_path, is_directory).is_ignore()
+            || (is_directory && contains_ignored(&path, &relative_path, ignore)?)
+        {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn unfold_directory(destination: &Path, args: HookArgs) -> Result<(), HookError> {
+    let target = destination
+        .canonicalize()
+        .map_err(|err| HookError::ExecutionError(format!("Error reading symlink: {}", err)))?;
+
+    if !args.quiet {
+        eprintln!(
+            "Unfolding directory: {} -> {}",
+            destination.display(),
+            target.display()
+        );
+    }
+
+    remove_file(destination, args.clone())?;
+    create_destination_directory(destination, args.clone())?;
+
+    for name in read_dir_sorted(&target)? {
+        let source = target.join(&name);
+        let destination = destination.join(&name);
+
+        if is_real_dir(&source) {
+            create_symlink_directory_op(&source, &destination, args.clone())?;
+
+            if args.plan.is_none() && !args.dry_run {
+                record_fold(&destination, &source)?;
+            }
+        } else {
+            create_symlink_file_op(&source, &destination, args.clone())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates the missing parents of the destination and source paths before `create`, removing them
+/// again if it fails. Parents created inside the source tree are given to the source owner.
+fn with_parent_directories(
+    destinations: &[&Path],
+    sources: &[&Path],
+    args: HookArgs,
+    create: impl FnOnce() -> Result<(), HookError>,
+) -> Result<(), HookError> {
+    let mut created_directories = Vec::new();
+
+    let paths = destinations
+        .iter()
+        .map(|path| (path, false))
+        .chain(sources.iter().map(|path| (path, true)));
+
+    let result = paths
+        .into_iter()
+        .try_for_each(|(path, in_source)| {
+            create_parent_directories(path, in_source, &mut created_directories, args.clone())
+        })
+        .and_then(|_| create());
+
+    if matches!(&result, Err(err) if !matches!(err, HookError::Skipping(_))) {
+        remove_created_directories(&created_directories, args);
+    }
+
+    result
+}
+
+fn create_parent_directories(
+    path: &Path,
+    in_source: bool,
+    created_directories: &mut Vec<PathBuf>,
+    args: HookArgs,
+) -> Result<(), HookError> {
+    let missing = path
+        .ancestors()
+        .skip(1)
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+        .collect::<Vec<_>>();
+
+    for directory in missing.into_iter().rev() {
+        if !args.quiet {
+            eprintln!("Creating parent directory: {}", directory.display());
+        }
 
-    let destination = get_path(&args.destination.replace(r"\\", r"/").replace(r"\", "/"))
-        .map_err(|err| HookError::ExecutionError(format!("Error getting source path: {}", err)))?;
+        if skip_operation(Step::CreateDirectory(directory.to_path_buf()), &args) {
+            if in_source {
+                give_to_source_owner(directory, directory, &args)?;
+            }
 
-    if args.verbose {
-        eprintln!("Source: {}", source.display());
-        eprintln!("Destination: {}", destination.display());
+            continue;
+        }
 
-        if args.relative {
-            eprintln!(
-                "Relative destination: {}",
-                pathdiff::diff_paths(&source, &destination)
-                    .unwrap_or_else(|| PathBuf::from("N/A"))
-                    .display()
-            );
+        std::fs::create_dir(directory).map_err(|err| {
+            HookError::ExecutionError(format!("Error creating parent directory: {}", err))
+        })?;
+
+        created_directories.push(directory.to_path_buf());
+
+        if !in_source {
+            record_created_directory(directory)?;
+        }
+
+        #[cfg(unix)]
+        if let Some(mode) = args.parent_mode {
+            use std::os::unix::fs::PermissionsExt;
+
+            std::fs::set_permissions(directory, std::fs::Permissions::from_mode(mode)).map_err(
+                |err| {
+                    HookError::ExecutionError(format!(
+                        "Error setting parent directory mode: {}",
+                        err
+                    ))
+                },
+            )?;
+        }
+
+        if in_source {
+            give_to_source_owner(directory, directory, &args)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn remove_created_directories(directories: &[PathBuf], args: HookArgs) {
+    for directory in directories.iter().rev() {
+        if !args.quiet {
+            eprintln!("Rolling back parent directory: {}", directory.display());
+        }
+
+        if let Err(err) = std::fs::remove_dir(directory) {
+            eprintln!("Error rolling back parent directory: {}", err);
+        } else if let Err(err) = forget_created_directory(directory) {
+            eprintln!("Error rolling back parent directory: {}", err);
         }
     }
+}
 
-    if source.file_name() != destination.file_name() {
-        return handle_different_base_names(source, destination, args.clone());
+fn set_mode(path: &Path, mode: u32, args: HookArgs) -> Result<(), HookError> {
+    if !args.quiet {
+        eprintln!("Setting mode: {} to {:04o}", path.display(), mode);
     }
 
-    check_valid_paths_and_create_symlink(source, destination, args)
+    if skip_operation(
+        Step::SetMode {
+            path: path.to_path_buf(),
+            mode,
+        },
+        &args,
+    ) {
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .map_err(|err| HookError::ExecutionError(format!("Error setting mode: {}", err)))?;
+    }
+
+    Ok(())
 }
 
-fn handle_different_base_names(
-    source: PathBuf,
-    destination: PathBuf,
+fn set_owner(
+    path: &Path,
+    uid: Option<u32>,
+    gid: Option<u32>,
     args: HookArgs,
 ) -> Result<(), HookError> {
-    if args.force {
-        return check_valid_paths_and_create_symlink(source, destination, args);
+    let step = Step::SetOwner {
+        path: path.to_path_buf(),
+        uid,
+        gid,
+    };
+
+    if !args.quiet {
+        eprintln!(
+            "Setting owner: {} to {}:{}",
+            path.display(),
+            uid.map_or_else(|| "-".to_string(), |uid| uid.to_string()),
+            gid.map_or_else(|| "-".to_string(), |gid| gid.to_string())
+        );
     }
 
-    if !args.interactive {
-        return Err(HookError::DifferentNames);
+    if skip_operation(step, &args) {
+        return Ok(());
     }
 
-    let expected_destination = destination.with_file_name(source.file_name().unwrap());
+    #[cfg(unix)]
+    std::os::unix::fs::chown(path, uid, gid)
+        .map_err(|err| HookError::ExecutionError(format!("Error setting owner: {}", err)))?;
+
+    Ok(())
+}
+
+fn remove_file(path: &Path, args: HookArgs) -> Result<(), HookError> {
+    check_not_protected(path, &args.protect)?;
+    confirm_large_removal(path, args.clone())?;
+
+    if !args.quiet {
+        eprintln!("Removing file: {}", path.display());
+    }
+
+    if skip_operation(Step::RemoveFile(path.to_path_buf()), &args) {
+        return Ok(());
+    }
+
+    std::fs::remove_file(path)
+        .map_err(|err| HookError::ExecutionError(format!("Error removing file: {}", err)))
+}
+
+fn remove_directory(path: &Path, args: HookArgs) -> Result<(), HookError> {
+    check_not_protected(path, &args.protect)?;
+    confirm_large_removal(path, args.clone())?;
+
+    if !args.quiet {
+        eprintln!("Removing directory: {}", path.display());
+    }
+
+    if skip_operation(Step::RemoveDirectory(path.to_path_buf()), &args) {
+        return Ok(());
+    }
+
+    std::fs::remove_dir_all(path)
+        .map_err(|err| HookError::ExecutionError(format!("Error removing directory: {}", err)))
+}
+
+/// Asks for confirmation before removing more than --max-delete-files files or
+/// --max-delete-bytes bytes, even when --force is passed. Nothing is removed while planning or
+/// doing a dry run, so there is nothing to confirm.
+fn confirm_large_removal(path: &Path, args: HookArgs) -> Result<(), HookError> {
+    if args.dry_run || args.plan.is_some() {
+        return Ok(());
+    }
+
+    let (files, bytes) = measure_removal(path);
+
+    if files <= args.max_delete_files && bytes <= args.max_delete_bytes {
+        return Ok(());
+    }
 
     eprintln!(
-        "Possible name error: The destination path does not have the same name as the source path."
+        "Removing {} would delete {} files ({} bytes).",
+        path.display(),
+        files,
+        bytes
     );
-    eprintln!("Source: {}", source.display());
-    eprintln!("Destination: {}", destination.display());
-    eprintln!("Expected destination: {}", expected_destination.display());
-    eprintln!("Which path did you mean to use? (d/e) OR (n) to cancel.");
+    eprintln!("Do you wish to continue? (y/n)");
 
     let mut input = String::new();
 
     loop {
         match std::io::stdin().read_line(&mut input) {
+            Ok(0) => return Err(HookError::CancelledByUser),
             Ok(_) => {
                 match input.trim() {
-                    "d" | "D" => {
-                        // Using inputted destination
-                        return check_valid_paths_and_create_symlink(source, destination, args);
-                    }
-                    "e" | "E" => {
-                        // Using expected destination
-                        return check_valid_paths_and_create_symlink(
-                            source,
-                            expected_destination,
-                            args,
-                        );
+                    "y" | "Y" => {
+                        // Continue
+                        return Ok(());
                     }
                     "n" | "N" => {
                         // Cancel
                         return Err(HookError::CancelledByUser);
                     }
                     _ => {
-                        eprintln!("Invalid input. Please enter 'd', 'e', or 'n'.");
+                        eprintln!("Invalid input. Please enter 'y' or 'n'.");
                         input.clear();
                     }
                 }
@@ -102,251 +1834,295 @@ fn handle_different_base_names(
     }
 }
 
-fn check_valid_paths_and_create_symlink(
-    source: PathBuf,
-    destination: PathBuf,
-    args: HookArgs,
-) -> Result<(), HookError> {
-    let source_is_file = source
-        .exists()
-        .then(|| source.metadata().unwrap().is_file());
-
-    let destination_is_file = destination
-        .exists()
-        .then(|| destination.metadata().unwrap().is_file());
+fn create_directory(path: &Path, args: HookArgs) -> Result<(), HookError> {
+    if !args.quiet {
+        eprintln!("Creating directory: {}", path.display());
+    }
 
-    match (source_is_file, destination_is_file) {
-        (None, None) => Err(HookError::PathsDontExist),
-        (Some(true), Some(false)) => Err(HookError::ExecutionError(
-            "The destination path must be a file if the source path is a file.".to_string(),
-        )),
-        (Some(false), Some(true)) => Err(HookError::ExecutionError(
-            "The destination path must be a directory if the source path is a directory."
-                .to_string(),
-        )),
-        (Some(true), None) => create_symlink_file(&source, &destination, args),
-        (None, Some(true)) => create_symlink_file(&source, &destination, args),
-        (Some(false), None) => create_symlink_directory(&source, &destination, args),
-        (None, Some(false)) => create_symlink_directory(&source, &destination, args),
-        (Some(true), Some(true)) => create_symlink_file(&source, &destination, args),
-        (Some(false), Some(false)) => create_symlink_directory(&source, &destination, args),
+    if skip_operation(Step::CreateDirectory(path.to_path_buf()), &args) {
+        return Ok(());
     }
+
+    std::fs::create_dir_all(path)
+        .map_err(|err| HookError::ExecutionError(format!("Error creating directory: {}", err)))
 }
 
-fn create_symlink_file(source: &Path, destination: &Path, args: HookArgs) -> Result<(), HookError> {
-    if destination.is_symlink() {
-        handle_symlink_different_target(source, destination, args.clone())?;
-    }
+/// Creates a directory for links made per file, recorded so unlinking can remove it again.
+fn create_destination_directory(path: &Path, args: HookArgs) -> Result<(), HookError> {
+    create_directory(path, args.clone())?;
 
-    if args.verbose {
-        eprintln!(
-            "Trying to create symlink file: {} -> {}",
-            destination.display(),
-            source.display(),
-        );
+    if args.plan.is_some() || args.dry_run {
+        return Ok(());
     }
 
-    match (destination.exists(), source.exists()) {
-        (false, true) => create_symlink_file_op(source, destination, args.clone()),
-        (true, false) => {
-            move_file(destination, source, args.clone())?;
-            create_symlink_file_op(source, destination, args.clone())
-        }
-        (true, true) => {
-            if !args.force && !args.interactive {
-                return Err(HookError::FilesAlreadyExists);
-            }
+    record_created_directory(path)
+}
 
-            if args.interactive {
-                eprintln!("The source and destination paths both exist.");
-                eprintln!("Source: {}", source.display());
-                eprintln!("Destination: {}", destination.display());
-                eprintln!("Which do you wish to keep? (s/d) OR (n) to cancel.");
+fn move_file(from: &Path, to: &Path, args: HookArgs) -> Result<(), HookError> {
+    check_not_protected(from, &args.protect)?;
 
-                let mut input = String::new();
+    if !args.quiet {
+        eprintln!("Moving file: {} to {}", from.display(), to.display());
+    }
 
-                loop {
-                    match std::io::stdin().read_line(&mut input) {
-                        Ok(_) => {
-                            match input.trim() {
-                                "s" | "S" => {
-                                    // Keeping source
-                                    remove_file(destination, args.clone())?;
-                                    break;
-                                }
-                                "d" | "D" => {
-                                    // Keeping destination
-                                    remove_file(source, args.clone())?;
-                                    move_file(destination, source, args.clone())?;
-                                    break;
-                                }
-                                "n" | "N" => {
-                                    // Cancel
-                                    return Err(HookError::CancelledByUser);
-                                }
-                                _ => {
-                                    eprintln!("Invalid input. Please enter 's', 'd', or 'n'.");
-                                    input.clear();
-                                }
-                            }
-                        }
-                        Err(err) => {
-                            return Err(HookError::ExecutionError(format!(
-                                "Error reading input: {}",
-                                err
-                            )));
-                        }
-                    }
-                }
-            } else {
-                // args.force is always true here
-                remove_file(destination, args.clone())?;
-            }
+    let step = Step::MoveFile {
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+    };
 
-            create_symlink_file_op(source, destination, args)
-        }
-        (false, false) => {
-            unreachable!(
-                "Check occurs in {}",
-                stringify!(check_valid_paths_and_create_symlink)
-            );
-        }
+    if skip_operation(step, &args) {
+        return give_to_source_owner(to, from, &args);
     }
+
+    std::fs::rename(from, to)
+        .map_err(|err| HookError::ExecutionError(format!("Error moving file: {}", err)))?;
+
+    give_to_source_owner(to, to, &args)
 }
 
-fn create_symlink_directory(
-    source: &Path,
-    destination: &Path,
-    args: HookArgs,
-) -> Result<(), HookError> {
-    if destination.is_symlink() {
-        handle_symlink_different_target(source, destination, args.clone())?;
+fn move_directory(from: &Path, to: &Path, args: HookArgs) -> Result<(), HookError> {
+    check_not_protected(from, &args.protect)?;
+
+    if !args.quiet {
+        eprintln!("Moving directory: {} to {}", from.display(), to.display());
     }
 
-    if args.verbose {
-        eprintln!(
-            "Trying to create symlink directory: {} -> {}",
-            destination.display(),
-            source.display(),
-        );
+    let step = Step::MoveDirectory {
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+    };
+
+    if skip_operation(step, &args) {
+        return give_to_source_owner(to, from, &args);
     }
 
-    assert!(source.extension().is_none());
-    assert!(destination.extension().is_none());
+    std::fs::rename(from, to)
+        .map_err(|err| HookError::ExecutionError(format!("Error moving directory: {}", err)))?;
 
-    match (destination.exists(), source.exists()) {
-        (false, true) => create_symlink_directory_op(source, destination, args),
-        (true, false) => {
-            move_directory(destination, source, args.clone())?;
-            create_symlink_directory_op(source, destination, args)
-        }
-        (true, true) => {
-            if is_dir_empty(destination) {
-                remove_directory(destination, args.clone())?;
-                return create_symlink_directory(source, destination, args);
-            } else if is_dir_empty(source) {
-                remove_directory(source, args.clone())?;
-                return create_symlink_directory(source, destination, args);
-            }
+    give_to_source_owner(to, to, &args)
+}
 
-            if !args.force && !args.interactive {
-                return Err(HookError::FilesAlreadyExists);
-            }
+fn create_source_directory(path: &Path, args: HookArgs) -> Result<(), HookError> {
+    create_directory(path, args.clone())?;
+    give_to_source_owner(path, path, &args)
+}
 
-            if args.interactive {
-                eprintln!("The source and destination paths both exist and have files in them.");
-                eprintln!("Source: {}", source.display());
-                eprintln!("Destination: {}", destination.display());
-                eprintln!("Which do you wish to keep? (s/d) OR (n) to cancel.");
+/// Hands a path moved or created inside the source tree, and everything inside it, to the source
+/// owner. Otherwise running hook with sudo would leave files owned by root in the user's source.
+/// `current` is where the files are found, which is still the old path while a move is planned.
+fn give_to_source_owner(path: &Path, current: &Path, args: &HookArgs) -> Result<(), HookError> {
+    let Some(owner) = args.source_owner else {
+        return Ok(());
+    };
+
+    if !args.quiet {
+        eprintln!(
+            "Giving to the source owner: {} to {}:{}",
+            path.display(),
+            owner.uid,
+            owner
+                .gid
+                .map_or_else(|| "-".to_string(), |gid| gid.to_string())
+        );
+    }
+
+    chown_tree(path, current, owner, args)
+}
 
-                let mut input = String::new();
-                loop {
-                    match std::io::stdin().read_line(&mut input) {
-                        Ok(_) => {
-                            match input.trim() {
-                                "s" | "S" => {
-                                    // Keeping source
-                                    remove_directory(destination, args.clone())?;
-                                    break;
-                                }
-                                "d" | "D" => {
-                                    // Keeping destination
-                                    remove_directory(source, args.clone())?;
-                                    move_directory(destination, source, args.clone())?;
-                                    break;
-                                }
-                                "n" | "N" => {
-                                    // Cancel
-                                    return Err(HookError::CancelledByUser);
-                                }
-                                _ => {
-                                    eprintln!("Invalid input. Please enter 's', 'd', or 'n'.");
-                                    input.clear();
-                                }
-                            }
-                        }
-                        Err(err) => {
-                            eprintln!("Error reading input: {}", err);
-                        }
-                    }
-                }
-            } else {
-                // args.force is always true here
-                remove_directory(destination, args.clone())?;
-            }
+fn chown_tree(
+    path: &Path,
+    current: &Path,
+    owner: SourceOwner,
+    args: &HookArgs,
+) -> Result<(), HookError> {
+    let step = Step::SetOwner {
+        path: path.to_path_buf(),
+        uid: Some(owner.uid),
+        gid: owner.gid,
+    };
 
-            create_symlink_directory_op(source, destination, args)
-        }
-        (false, false) => {
-            create_directory(source, args.clone())?;
-            create_symlink_directory_op(source, destination, args)
+    if !skip_operation(step, args) {
+        #[cfg(unix)]
+        std::os::unix::fs::lchown(path, Some(owner.uid), owner.gid)
+            .map_err(|err| HookError::ExecutionError(format!("Error setting owner: {}", err)))?;
+    }
+
+    if is_real_dir(current) {
+        for name in read_dir_sorted(current)? {
+            chown_tree(&path.join(&name), &current.join(&name), owner, args)?;
         }
     }
+
+    Ok(())
 }
 
-fn remove_file(path: &Path, args: HookArgs) -> Result<(), HookError> {
+fn create_file_link_op(source: &Path, destination: &Path, args: HookArgs) -> Result<(), HookError> {
+    match args.link_type() {
+        LinkType::Symlink => create_symlink_file_op(source, destination, args),
+        LinkType::Hard => create_hard_link_op(source, destination, args),
+        LinkType::Copy | LinkType::Reflink => create_copy_op(source, destination, args),
+        LinkType::Template => create_render_op(source, destination, args),
+    }
+}
+
+fn create_render_op(source: &Path, destination: &Path, args: HookArgs) -> Result<(), HookError> {
+    let content = render_file(source, &args)?;
+
     if !args.quiet {
-        eprintln!("Removing file: {}", path.display());
+        eprintln!(
+            "Rendering template: {} to {}",
+            source.display(),
+            destination.display(),
+        );
     }
 
-    std::fs::remove_file(path)
-        .map_err(|err| HookError::ExecutionError(format!("Error removing file: {}", err)))
+    let step = Step::Render {
+        source: source.to_path_buf(),
+        destination: destination.to_path_buf(),
+        content: content.clone(),
+    };
+
+    if skip_operation(step, &args) {
+        return Ok(());
+    }
+
+    std::fs::write(destination, content)
+        .map_err(|err| HookError::ExecutionError(format!("Error writing file: {}", err)))?;
+
+    // The rendering keeps the mode of the template, so rendered scripts stay executable.
+    source
+        .metadata()
+        .and_then(|metadata| std::fs::set_permissions(destination, metadata.permissions()))
+        .map_err(|err| HookError::ExecutionError(format!("Error setting mode: {}", err)))?;
+
+    record_copy(source, destination, LinkType::Template)
 }
 
-fn remove_directory(path: &Path, args: HookArgs) -> Result<(), HookError> {
+/// The record of the destination as a copy of the source, if it was made by an earlier run.
+fn find_copy_record(source: &Path, destination: &Path) -> Result<Option<CopyRecord>, HookError> {
+    Ok(load_copy_records()?.into_iter().find(|record| {
+        record.link_type != LinkType::Template
+            && record.source == source
+            && record.destination == destination
+    }))
+}
+
+/// Copies whichever side of a recorded copy changed since the last sync over the other. Copies
+/// changed on both sides are conflicts, which --force resolves by copying the source again.
+fn update_copy(record: &CopyRecord, args: HookArgs) -> Result<(), HookError> {
+    let source_changed = hash_file(&record.source)? != record.hash;
+    let destination_changed = hash_file(&record.destination)? != record.hash;
+
+    let (from, to) = match (source_changed, destination_changed) {
+        (true, false) => (&record.source, &record.destination),
+        (false, true) => (&record.destination, &record.source),
+        _ => return Err(HookError::SyncConflicts(1)),
+    };
+
+    let reflink = args.link_type() == LinkType::Reflink;
+
     if !args.quiet {
-        eprintln!("Removing directory: {}", path.display());
+        eprintln!("Updating copy: {} to {}", from.display(), to.display());
     }
 
-    std::fs::remove_dir_all(path)
-        .map_err(|err| HookError::ExecutionError(format!("Error removing directory: {}", err)))
+    let step = Step::Copy {
+        source: from.clone(),
+        destination: to.clone(),
+        reflink,
+    };
+
+    if skip_operation(step, &args) {
+        return Ok(());
+    }
+
+    copy_file(from, to, reflink)?;
+
+    record_copy(&record.source, &record.destination, args.link_type())
 }
 
-fn create_directory(path: &Path, args: HookArgs) -> Result<(), HookError> {
+/// Whether the destination was rendered from the source by an earlier run and still holds that
+/// output.
+fn is_unchanged_rendering(source: &Path, destination: &Path) -> Result<bool, HookError> {
+    let hash = hash_file(destination)?;
+
+    Ok(load_copy_records()?.iter().any(|record| {
+        record.link_type == LinkType::Template
+            && record.source == source
+            && record.destination == destination
+            && record.hash == hash
+    }))
+}
+
+fn create_copy_op(source: &Path, destination: &Path, args: HookArgs) -> Result<(), HookError> {
+    let reflink = args.link_type() == LinkType::Reflink;
+
     if !args.quiet {
-        eprintln!("Creating directory: {}", path.display());
+        eprintln!(
+            "{} file: {} to {}",
+            if reflink { "Cloning" } else { "Copying" },
+            source.display(),
+            destination.display(),
+        );
     }
 
-    std::fs::create_dir_all(path)
-        .map_err(|err| HookError::ExecutionError(format!("Error creating directory: {}", err)))
+    let step = Step::Copy {
+        source: source.to_path_buf(),
+        destination: destination.to_path_buf(),
+        reflink,
+    };
+
+    if skip_operation(step, &args) {
+        return Ok(());
+    }
+
+    copy_file(source, destination, reflink)?;
+
+    record_copy(source, destination, args.link_type())
 }
 
-fn move_file(from: &Path, to: &Path, args: HookArgs) -> Result<(), HookError> {
-    if !args.quiet {
-        eprintln!("Moving file: {} to {}", from.display(), to.display());
+fn copy_file(source: &Path, destination: &Path, reflink: bool) -> Result<(), HookError> {
+    let result = if reflink {
+        reflink_or_copy(source, destination)
+    } else {
+        std::fs::copy(source, destination).map(|_| ())
+    };
+
+    result.map_err(|err| HookError::ExecutionError(format!("Error copying file: {}", err)))
+}
+
+fn is_same_content(source: &Path, destination: &Path) -> bool {
+    if !source.is_file() || !destination.is_file() || destination.is_symlink() {
+        return false;
     }
 
-    std::fs::rename(from, to)
-        .map_err(|err| HookError::ExecutionError(format!("Error moving file: {}", err)))
+    matches!(
+        (hash_file(source), hash_file(destination)),
+        (Ok(source_hash), Ok(destination_hash)) if source_hash == destination_hash
+    )
 }
 
-fn move_directory(from: &Path, to: &Path, args: HookArgs) -> Result<(), HookError> {
+fn create_hard_link_op(source: &Path, destination: &Path, args: HookArgs) -> Result<(), HookError> {
+    check_same_device(source, destination)?;
+
     if !args.quiet {
-        eprintln!("Moving directory: {} to {}", from.display(), to.display());
+        eprintln!(
+            "Creating hard link: {} => {}",
+            destination.display(),
+            source.display(),
+        );
     }
 
-    std::fs::rename(from, to)
-        .map_err(|err| HookError::ExecutionError(format!("Error moving directory: {}", err)))
+    let step = Step::HardLink {
+        source: source.to_path_buf(),
+        destination: destination.to_path_buf(),
+    };
+
+    if skip_operation(step, &args) {
+        return Ok(());
+    }
+
+    std::fs::hard_link(source, destination)
+        .map_err(|err| HookError::ExecutionError(format!("Error creating hard link: {}", err)))
 }
 
 fn create_symlink_file_op(
@@ -354,32 +2130,28 @@ fn create_symlink_file_op(
     destination: &Path,
     args: HookArgs,
 ) -> Result<(), HookError> {
-    let source = if args.relative {
-        let parent = destination.parent().ok_or_else(|| {
-            HookError::ExecutionError("Destination path has no parent".to_string())
-        })?;
-
-        &pathdiff::diff_paths(source, parent).ok_or_else(|| HookError::PathDiff {
-            source: source.to_path_buf(),
-            destination: destination.to_path_buf(),
-        })?
-    } else {
-        source
-    };
+    let target = symlink_target(source, destination, args.clone())?;
 
     if !args.quiet {
         eprintln!(
             "Creating symlink: {} -> {}",
             destination.display(),
-            source.display(),
+            target.display(),
         );
     }
 
-    if args.dry_run {
+    let step = Step::Symlink {
+        source: source.to_path_buf(),
+        target: target.clone(),
+        destination: destination.to_path_buf(),
+        directory: false,
+    };
+
+    if skip_operation(step, &args) {
         return Ok(());
     }
 
-    symlink_file(source, destination).map_err(HookError::SymlinkCreationError)
+    symlink_file(target, destination).map_err(HookError::SymlinkCreationError)
 }
 
 fn create_symlink_directory_op(
@@ -387,32 +2159,178 @@ fn create_symlink_directory_op(
     destination: &Path,
     args: HookArgs,
 ) -> Result<(), HookError> {
-    let source = if args.relative {
-        let parent = destination.parent().ok_or_else(|| {
-            HookError::ExecutionError("Destination path has no parent".to_string())
-        })?;
-
-        &pathdiff::diff_paths(source, parent).ok_or_else(|| HookError::PathDiff {
-            source: source.to_path_buf(),
-            destination: destination.to_path_buf(),
-        })?
-    } else {
-        source
-    };
+    let target = symlink_target(source, destination, args.clone())?;
 
     if !args.quiet {
         eprintln!(
             "Creating symlink: {} -> {}",
             destination.display(),
-            source.display(),
+            target.display(),
         );
     }
 
-    if args.dry_run {
+    let step = Step::Symlink {
+        source: source.to_path_buf(),
+        target: target.clone(),
+        destination: destination.to_path_buf(),
+        directory: true,
+    };
+
+    if skip_operation(step, &args) {
         return Ok(());
     }
 
-    symlink_dir(source, destination).map_err(HookError::SymlinkCreationError)
+    symlink_dir(target, destination).map_err(HookError::SymlinkCreationError)
+}
+
+/// Records the step when exporting. Returns whether the operation should be skipped, which is
+/// also the case for dry runs.
+fn skip_operation(step: Step, args: &HookArgs) -> bool {
+    if let Some(plan) = &args.plan {
+        plan.record(step);
+        return true;
+    }
+
+    args.dry_run
+}
+
+/// The path the symlink at the destination should point to, as it will appear at runtime when
+/// --root is passed.
+fn symlink_target(source: &Path, destination: &Path, args: HookArgs) -> Result<PathBuf, HookError> {
+    if args.relative {
+        let parent = destination.parent().ok_or_else(|| {
+            HookError::ExecutionError("Destination path has no parent".to_string())
+        })?;
+
+        return pathdiff::diff_paths(source, parent).ok_or_else(|| HookError::PathDiff {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+        });
+    }
+
+    match get_root(&args)? {
+        Some(root) => Ok(strip_root(source, &root)),
+        None => Ok(source.to_path_buf()),
+    }
+}
+
+/// Removes what linking the source placed at the destination: a symlink to it, a hard link to it
+/// or a copy or rendering which wasn't changed since. Real directories are descended into, so
+/// links made per file are removed while anything else is left in place.
+fn remove_links(source: &Path, destination: &Path, args: HookArgs) -> Result<(), HookError> {
+    if destination.is_symlink() {
+        let target = destination
+            .read_link()
+            .map_err(|err| HookError::ExecutionError(format!("Error reading symlink: {}", err)))?;
+
+        let links_to_source = target == source
+            || target == symlink_target(source, destination, args.clone())?
+            || matches!(
+                (destination.canonicalize(), source.canonicalize()),
+                (Ok(a), Ok(b)) if a == b
+            );
+
+        if !links_to_source {
+            return Err(HookError::Skipping(format!(
+                "The destination path is a symlink to another path | Source: {} | Destination: {}",
+                source.display(),
+                destination.display()
+            )));
+        }
+
+        remove_file(destination, args.clone())?;
+        return remove_created_directories_around(destination, args);
+    }
+
+    if is_real_dir(destination) && source.is_dir() {
+        for name in read_dir_sorted(destination)? {
+            let source = resolve_alternate(source.join(&name), &args)?;
+
+            match remove_links(&source, &destination.join(&name), args.clone()) {
+                Err(HookError::Skipping(message)) => {
+                    if args.verbose {
+                        eprintln!("Skipping: {}", message);
+                    }
+                }
+                result => result?,
+            }
+        }
+
+        return remove_created_directories_around(destination, args);
+    }
+
+    if destination.is_file()
+        && (is_same_file(source, destination) || is_unchanged_copy(destination)?)
+    {
+        remove_file(destination, args.clone())?;
+
+        if args.plan.is_none() && !args.dry_run {
+            forget_copy(destination)?;
+        }
+
+        return remove_created_directories_around(destination, args);
+    }
+
+    Err(HookError::Skipping(format!(
+        "The destination path is not linked to the source path | Source: {} | Destination: {}",
+        source.display(),
+        destination.display()
+    )))
+}
+
+/// Removes the directories linking created once unlinking left them empty, from the path itself
+/// up to the first directory which existed before.
+fn remove_created_directories_around(path: &Path, args: HookArgs) -> Result<(), HookError> {
+    let created = load_created_directories()?;
+
+    // The path which was removed from the directory looked at, or is only planned to be.
+    let mut removed = None;
+
+    for directory in path.ancestors() {
+        if directory == path && !is_real_dir(directory) {
+            removed = Some(directory);
+            continue;
+        }
+
+        let empty = std::fs::read_dir(directory).is_ok_and(|mut entries| {
+            entries.all(|entry| entry.is_ok_and(|entry| Some(entry.path().as_path()) == removed))
+        });
+
+        if !created.contains(directory) || !is_real_dir(directory) || !empty {
+            break;
+        }
+
+        remove_directory(directory, args.clone())?;
+
+        if args.plan.is_none() && !args.dry_run {
+            forget_created_directory(directory)?;
+        }
+
+        removed = Some(directory);
+    }
+
+    Ok(())
+}
+
+/// The files inside the directory and its subdirectories, relative to it.
+fn files_in_tree(directory: &Path) -> Result<Vec<PathBuf>, HookError> {
+    let mut files = Vec::new();
+
+    for name in read_dir_sorted(directory)? {
+        let path = directory.join(&name);
+
+        if is_real_dir(&path) {
+            files.extend(
+                files_in_tree(&path)?
+                    .into_iter()
+                    .map(|relative_path| Path::new(&name).join(relative_path)),
+            );
+        } else if path.is_file() && !path.is_symlink() {
+            files.push(PathBuf::from(name));
+        }
+    }
+
+    Ok(files)
 }
 
 fn is_dir_empty(path: &Path) -> bool {
@@ -422,6 +2340,24 @@ fn is_dir_empty(path: &Path) -> bool {
     }
 }
 
+fn is_real_dir(path: &Path) -> bool {
+    path.symlink_metadata()
+        .map(|metadata| metadata.is_dir())
+        .unwrap_or(false)
+}
+
+fn read_dir_sorted(path: &Path) -> Result<Vec<OsString>, HookError> {
+    let mut names = std::fs::read_dir(path)
+        .and_then(|dir| {
+            dir.map(|entry| entry.map(|entry| entry.file_name()))
+                .collect::<std::io::Result<Vec<_>>>()
+        })
+        .map_err(|err| HookError::ExecutionError(format!("Error reading directory: {}", err)))?;
+
+    names.sort();
+    Ok(names)
+}
+
 fn handle_symlink_different_target(
     source: &Path,
     destination: &Path,
@@ -431,7 +2367,10 @@ fn handle_symlink_different_target(
         .read_link()
         .map_err(|err| HookError::ExecutionError(format!("Error reading symlink: {}", err)))?;
 
-    if target == source {
+    // An existing symlink is replaced by other link types, even if it points to the source.
+    if args.link_type() == LinkType::Symlink
+        && (target == source || target == symlink_target(source, destination, args.clone())?)
+    {
         return Err(HookError::Skipping(format!("The destination path is already a symlink to the source path | Source: {} | Destination: {}", source.display(), destination.display())));
     }
 
@@ -481,3 +2420,177 @@ fn handle_symlink_different_target(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("hook-program-{}-{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn write_file(path: &Path) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, path.to_string_lossy().as_bytes()).unwrap();
+    }
+
+    fn hook_args(source: &Path, destination: &Path, flags: &[&str]) -> HookArgs {
+        let paths = ["--source", source.to_str().unwrap()]
+            .into_iter()
+            .chain(["--destination", destination.to_str().unwrap()]);
+
+        Cli::parse_from(
+            ["hook", "--quiet"]
+                .into_iter()
+                .chain(paths)
+                .chain(flags.iter().copied()),
+        )
+        .args
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mode_of_directory_entry() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = |path: &Path| path.metadata().unwrap().permissions().mode() & 0o7777;
+
+        let directory = test_directory("mode");
+        let source = directory.join("repo/ssh");
+        let destination = directory.join("home/.ssh");
+
+        write_file(&source.join("config"));
+        write_file(&source.join("keys/id"));
+        std::fs::create_dir(directory.join("home")).unwrap();
+        std::fs::set_permissions(&source, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut args = hook_args(&source, &destination, &["--per-file"]);
+        args.permissions = Some(RequiredPermissions {
+            mode: Some(0o600),
+            ..Default::default()
+        });
+
+        check_valid_paths_and_create_symlink(source.clone(), destination.clone(), args).unwrap();
+
+        assert_eq!(mode(&source.join("config")), 0o600);
+        assert_eq!(mode(&source.join("keys/id")), 0o600);
+        assert_eq!(mode(&source), 0o755);
+        assert_eq!(
+            destination.join("keys/id").read_link().unwrap(),
+            source.join("keys/id")
+        );
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_fold_and_unfold() {
+        let directory = test_directory("fold");
+        let first = directory.join("repo/first");
+        let second = directory.join("repo/second");
+        let third = directory.join("repo/third");
+        let other = directory.join("other");
+        let home = directory.join("home");
+
+        write_file(&first.join("nvim/init.lua"));
+        write_file(&first.join("git/config"));
+        write_file(&first.join("git/config.swp"));
+        write_file(&second.join("nvim/lazy.lua"));
+        write_file(&third.join("fish/config.fish"));
+        write_file(&other.join("fish/functions.fish"));
+        std::fs::create_dir(&home).unwrap();
+
+        // A directory missing at the destination is linked whole and recorded as folded.
+        let args = hook_args(&first, &home, &["--fold", "--ignore", "*.swp"]);
+        check_valid_paths_and_create_symlink(first.clone(), home.clone(), args).unwrap();
+
+        assert_eq!(home.join("nvim").read_link().unwrap(), first.join("nvim"));
+        assert!(load_folds()
+            .unwrap()
+            .contains(&(home.join("nvim"), first.join("nvim"))));
+
+        // A directory with ignored files inside is linked file by file instead.
+        assert!(!home.join("git").is_symlink());
+        assert!(home.join("git/config").is_symlink());
+        assert!(!home.join("git/config.swp").exists());
+
+        // The folded directory is unfolded once a second source adds to it.
+        let args = hook_args(&second, &home, &["--fold"]);
+        check_valid_paths_and_create_symlink(second.clone(), home.clone(), args).unwrap();
+
+        assert!(!home.join("nvim").is_symlink());
+        assert_eq!(
+            home.join("nvim/init.lua").read_link().unwrap(),
+            first.join("nvim/init.lua")
+        );
+        assert_eq!(
+            home.join("nvim/lazy.lua").read_link().unwrap(),
+            second.join("nvim/lazy.lua")
+        );
+
+        // A symlink hook didn't create is left alone instead of being unfolded.
+        symlink_dir(other.join("fish"), home.join("fish")).unwrap();
+
+        let args = hook_args(&third, &home, &["--fold"]);
+        let result = check_valid_paths_and_create_symlink(third.clone(), home.clone(), args);
+
+        assert!(matches!(result, Err(HookError::FilesAlreadyExists)));
+        assert_eq!(home.join("fish").read_link().unwrap(), other.join("fish"));
+        assert!(!other.join("fish/config.fish").exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_layered_packages() {
+        let directory = test_directory("layers");
+        let base = directory.join("repo/base");
+        let work = directory.join("repo/work");
+        let home = directory.join("home");
+
+        write_file(&base.join(".gitconfig"));
+        write_file(&base.join(".config/git/ignore"));
+        write_file(&work.join(".gitconfig"));
+        write_file(&work.join(".config/git/attributes"));
+        std::fs::create_dir(&home).unwrap();
+
+        let link = |sources: &[PathBuf]| {
+            let args = hook_args(&sources[0], &home, &["--per-file"]);
+            let ignore = build_ignore(sources, &args.ignore).unwrap();
+            create_symlink_packages(sources, &home, &ignore, args).unwrap();
+        };
+
+        link(std::slice::from_ref(&base));
+
+        assert_eq!(
+            home.join(".gitconfig").read_link().unwrap(),
+            base.join(".gitconfig")
+        );
+
+        // The link to the package with lower priority is replaced without --force.
+        link(&[base.clone(), work.clone()]);
+
+        assert_eq!(
+            home.join(".gitconfig").read_link().unwrap(),
+            work.join(".gitconfig")
+        );
+
+        // Directories provided by both packages are merged.
+        assert!(!home.join(".config/git").is_symlink());
+        assert_eq!(
+            home.join(".config/git/ignore").read_link().unwrap(),
+            base.join(".config/git/ignore")
+        );
+        assert_eq!(
+            home.join(".config/git/attributes").read_link().unwrap(),
+            work.join(".config/git/attributes")
+        );
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+use std::{path::Path, process::Command};
+
+use crate::{args::HookArgs, error::HookError};
+
+/// Runs each command with the shell inside the directory, stopping at the first one which fails.
+/// The environment describes what the commands run for. With --dry-run they are only printed.
+pub fn run_commands(
+    commands: &[String],
+    directory: &Path,
+    env: &[(&str, String)],
+    args: &HookArgs,
+) -> Result<(), HookError> {
+    for command in commands {
+        if !args.quiet {
+            eprintln!("Running: {}", command);
+        }
+
+        if args.dry_run {
+            continue;
+        }
+
+        let status = shell(command)
+            .current_dir(directory)
+            .envs(env.iter().map(|(name, value)| (name, value)))
+            .status()
+            .map_err(|err| {
+                HookError::ExecutionError(format!("Error running {}: {}", command, err))
+            })?;
+
+        if !status.success() {
+            return Err(HookError::CommandFailed {
+                command: command.clone(),
+                status: status.to_string(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
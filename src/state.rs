@@ -0,0 +1,292 @@
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{args::LinkType, error::HookError, permissions::is_root};
+
+/// A file placed at the destination as a copy of the source, with the hash both had when they
+/// were last in sync. For templates the hash is that of the rendered output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopyRecord {
+    pub link_type: LinkType,
+    pub hash: String,
+    pub source: PathBuf,
+    pub destination: PathBuf,
+}
+
+fn state_file(name: &str) -> Result<PathBuf, HookError> {
+    data_directory()
+        .map(|directory| directory.join("hook").join(name))
+        .ok_or_else(|| HookError::ExecutionError("Couldn't find the data directory".to_string()))
+}
+
+#[cfg(not(test))]
+fn data_directory() -> Option<PathBuf> {
+    dirs::data_local_dir()
+}
+
+/// Tests keep their state apart from the user's, and from each other since they run in parallel.
+#[cfg(test)]
+fn data_directory() -> Option<PathBuf> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT_TEST: AtomicUsize = AtomicUsize::new(0);
+
+    thread_local! {
+        static DIRECTORY: PathBuf = std::env::temp_dir().join(format!(
+            "hook-state-{}-{}",
+            std::process::id(),
+            NEXT_TEST.fetch_add(1, Ordering::Relaxed)
+        ));
+    }
+
+    Some(DIRECTORY.with(PathBuf::clone))
+}
+
+fn write_state(state_file: &Path, content: String) -> Result<(), HookError> {
+    if let Some(parent) = state_file.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| HookError::ExecutionError(format!("Error writing state: {}", err)))?;
+    }
+
+    std::fs::write(state_file, content)
+        .map_err(|err| HookError::ExecutionError(format!("Error writing state: {}", err)))?;
+
+    give_to_data_owner(state_file)
+}
+
+/// Gives state written as root, such as by a run through --escalate, to the owner of the data
+/// directory. Otherwise later runs without root couldn't update it.
+fn give_to_data_owner(state_file: &Path) -> Result<(), HookError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let (Some(state_directory), true) = (state_file.parent(), is_root()) else {
+            return Ok(());
+        };
+
+        let Some(data_directory) = state_directory.parent() else {
+            return Ok(());
+        };
+
+        let owner = data_directory
+            .metadata()
+            .map_err(|err| HookError::ExecutionError(format!("Error reading state: {}", err)))?;
+
+        if owner.uid() == 0 {
+            return Ok(());
+        }
+
+        for path in [state_directory, state_file] {
+            std::os::unix::fs::chown(path, Some(owner.uid()), Some(owner.gid())).map_err(
+                |err| HookError::ExecutionError(format!("Error writing state: {}", err)),
+            )?;
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = state_file;
+
+    Ok(())
+}
+
+/// Loads the copies recorded in the state file, one `link type<TAB>hash<TAB>source<TAB>destination`
+/// per line.
+pub fn load_copy_records() -> Result<Vec<CopyRecord>, HookError> {
+    let state_file = state_file("copies")?;
+
+    if !state_file.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = std::fs::read_to_string(&state_file)
+        .map_err(|err| HookError::ExecutionError(format!("Error reading state: {}", err)))?;
+
+    Ok(content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+
+            let link_type = match fields.next()? {
+                "copy" => LinkType::Copy,
+                "reflink" => LinkType::Reflink,
+                "template" => LinkType::Template,
+                _ => return None,
+            };
+
+            Some(CopyRecord {
+                link_type,
+                hash: fields.next()?.to_string(),
+                source: PathBuf::from(fields.next()?),
+                destination: PathBuf::from(fields.next()?),
+            })
+        })
+        .collect())
+}
+
+pub fn save_copy_records(records: &[CopyRecord]) -> Result<(), HookError> {
+    let content = records
+        .iter()
+        .map(|record| {
+            let link_type = match record.link_type {
+                LinkType::Reflink => "reflink",
+                LinkType::Template => "template",
+                _ => "copy",
+            };
+
+            format!(
+                "{}\t{}\t{}\t{}\n",
+                link_type,
+                record.hash,
+                record.source.display(),
+                record.destination.display()
+            )
+        })
+        .collect::<String>();
+
+    write_state(&state_file("copies")?, content)
+}
+
+/// Records the destination as an in sync copy or rendering of the source, replacing any earlier
+/// record for the same destination.
+pub fn record_copy(
+    source: &Path,
+    destination: &Path,
+    link_type: LinkType,
+) -> Result<(), HookError> {
+    let hash = hash_file(destination)?;
+    let mut records = load_copy_records()?;
+
+    records.retain(|record| record.destination != destination);
+    records.push(CopyRecord {
+        link_type,
+        hash,
+        source: source.to_path_buf(),
+        destination: destination.to_path_buf(),
+    });
+
+    save_copy_records(&records)
+}
+
+/// Whether the destination is recorded as a copy or rendering which hasn't changed since.
+pub fn is_unchanged_copy(destination: &Path) -> Result<bool, HookError> {
+    let hash = hash_file(destination)?;
+
+    Ok(load_copy_records()?
+        .iter()
+        .any(|record| record.destination == destination && record.hash == hash))
+}
+
+pub fn forget_copy(destination: &Path) -> Result<(), HookError> {
+    let mut records = load_copy_records()?;
+    records.retain(|record| record.destination != destination);
+    save_copy_records(&records)
+}
+
+/// Loads the directories created for destinations, so unlinking can remove them again once
+/// they are empty. The state file lists one per line.
+pub fn load_created_directories() -> Result<BTreeSet<PathBuf>, HookError> {
+    let state_file = state_file("directories")?;
+
+    if !state_file.exists() {
+        return Ok(BTreeSet::new());
+    }
+
+    let content = std::fs::read_to_string(&state_file)
+        .map_err(|err| HookError::ExecutionError(format!("Error reading state: {}", err)))?;
+
+    Ok(content.lines().map(PathBuf::from).collect())
+}
+
+fn save_created_directories(directories: &BTreeSet<PathBuf>) -> Result<(), HookError> {
+    let content = directories
+        .iter()
+        .map(|directory| format!("{}\n", directory.display()))
+        .collect::<String>();
+
+    write_state(&state_file("directories")?, content)
+}
+
+pub fn record_created_directory(directory: &Path) -> Result<(), HookError> {
+    let mut directories = load_created_directories()?;
+
+    if directories.insert(directory.to_path_buf()) {
+        save_created_directories(&directories)?;
+    }
+
+    Ok(())
+}
+
+pub fn forget_created_directory(directory: &Path) -> Result<(), HookError> {
+    let mut directories = load_created_directories()?;
+
+    if directories.remove(directory) {
+        save_created_directories(&directories)?;
+    }
+
+    Ok(())
+}
+
+/// Loads the directories linked whole by --fold, as `destination<TAB>source` per line, so linking
+/// another source into one of them knows it may unfold it.
+pub fn load_folds() -> Result<Vec<(PathBuf, PathBuf)>, HookError> {
+    let state_file = state_file("folds")?;
+
+    if !state_file.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = std::fs::read_to_string(&state_file)
+        .map_err(|err| HookError::ExecutionError(format!("Error reading state: {}", err)))?;
+
+    Ok(content
+        .lines()
+        .filter_map(|line| {
+            let (destination, source) = line.split_once('\t')?;
+            Some((PathBuf::from(destination), PathBuf::from(source)))
+        })
+        .collect())
+}
+
+pub fn record_fold(destination: &Path, source: &Path) -> Result<(), HookError> {
+    let mut folds = load_folds()?;
+
+    folds.retain(|(folded, _)| folded != destination);
+    folds.push((destination.to_path_buf(), source.to_path_buf()));
+
+    let content = folds
+        .iter()
+        .map(|(destination, source)| format!("{}\t{}\n", destination.display(), source.display()))
+        .collect::<String>();
+
+    write_state(&state_file("folds")?, content)
+}
+
+pub fn hash_file(path: &Path) -> Result<String, HookError> {
+    let mut file = File::open(path).map_err(|err| {
+        HookError::ExecutionError(format!("Error reading {}: {}", path.display(), err))
+    })?;
+
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 8192];
+
+    loop {
+        let read = file.read(&mut buffer).map_err(|err| {
+            HookError::ExecutionError(format!("Error reading {}: {}", path.display(), err))
+        })?;
+
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
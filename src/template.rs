@@ -0,0 +1,97 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{args::HookArgs, error::HookError};
+
+/// Collects the variables available to templates. Host facts such as `hostname` and `os` come
+/// first, environment variables are available as `env.NAME`, and `--var` overrides both.
+pub fn template_vars(args: &HookArgs) -> HashMap<String, String> {
+    let mut vars = args.alternate_context().facts().clone();
+
+    vars.extend(std::env::vars().map(|(name, value)| (format!("env.{}", name), value)));
+    vars.extend(args.vars.iter().cloned());
+
+    vars
+}
+
+/// Renders the template file at the given path with the variables from the arguments.
+pub fn render_file(path: &Path, args: &HookArgs) -> Result<String, HookError> {
+    let template = std::fs::read_to_string(path).map_err(|err| {
+        HookError::ExecutionError(format!("Error reading {}: {}", path.display(), err))
+    })?;
+
+    render(&template, &template_vars(args)).map_err(|err| {
+        HookError::ExecutionError(format!("Error rendering {}: {}", path.display(), err))
+    })
+}
+
+/// Replaces every `{{ name }}` in the template with the value of the variable. A quoted string
+/// such as `{{ '{{' }}` is written as it is, for files which use the same syntax themselves.
+pub fn render(template: &str, vars: &HashMap<String, String>) -> Result<String, String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+
+        let expression = rest[start + 2..].trim_start();
+
+        let (value, tail) = match expression.strip_prefix('\'') {
+            Some(quoted) => {
+                let Some(end) = quoted.find('\'') else {
+                    return Err("Unclosed string in template".to_string());
+                };
+
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => {
+                let Some(end) = expression.find("}}") else {
+                    return Err("Unclosed {{ in template".to_string());
+                };
+
+                let name = expression[..end].trim();
+
+                match vars.get(name) {
+                    Some(value) => (value.as_str(), &expression[end..]),
+                    None => return Err(format!("Unknown template variable: {}", name)),
+                }
+            }
+        };
+
+        let Some(tail) = tail.trim_start().strip_prefix("}}") else {
+            return Err("Unclosed {{ in template".to_string());
+        };
+
+        output.push_str(value);
+        rest = tail;
+    }
+
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let vars = HashMap::from([
+            ("user".to_string(), "me".to_string()),
+            ("env.EDITOR".to_string(), "nvim".to_string()),
+        ]);
+
+        assert_eq!(
+            render("name = {{ user }}\neditor = {{env.EDITOR}}\n", &vars).unwrap(),
+            "name = me\neditor = nvim\n"
+        );
+        assert!(render("{{ missing }}", &vars).is_err());
+        assert!(render("{{ user", &vars).is_err());
+        assert_eq!(
+            render("{{ '{{' }} user {{ '}}' }} is {{ user }}", &vars).unwrap(),
+            "{{ user }} is me"
+        );
+        assert!(render("{{ '{{ }}", &vars).is_err());
+        assert!(render("{{ 'a' b }}", &vars).is_err());
+    }
+}
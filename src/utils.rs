@@ -1,6 +1,6 @@
 use std::{
     io::{ErrorKind, Result},
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf, MAIN_SEPARATOR_STR},
 };
 
 pub fn get_path(path: &str) -> Result<PathBuf> {
@@ -39,6 +39,103 @@ pub fn get_path(path: &str) -> Result<PathBuf> {
     Ok(path)
 }
 
+/// Resolves the path like `get_path`, but places it inside `root`. Relative paths are resolved
+/// from the current directory and only placed inside `root` when they aren't already. `..` never
+/// leads outside of `root`.
+pub fn get_path_in_root(path: &str, root: &Path) -> Result<PathBuf> {
+    let full_path = get_path(path)?;
+    let mut relative_path = PathBuf::new();
+
+    for component in full_path.components() {
+        match component {
+            Component::Normal(name) => relative_path.push(name),
+            Component::ParentDir => {
+                relative_path.pop();
+            }
+            _ => {}
+        }
+    }
+
+    let normalized_path = Path::new(MAIN_SEPARATOR_STR).join(&relative_path);
+    if !path.starts_with('~') && !Path::new(path).has_root() && normalized_path.starts_with(root) {
+        return Ok(normalized_path);
+    }
+
+    Ok(root.join(relative_path))
+}
+
+/// Turns a path inside `root` into the path it will have at runtime.
+pub fn strip_root(path: &Path, root: &Path) -> PathBuf {
+    match path.strip_prefix(root) {
+        Ok(relative_path) => Path::new(MAIN_SEPARATOR_STR).join(relative_path),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Whether both paths exist and refer to the same file, such as two hard links to it.
+pub fn is_same_file(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        match (a.metadata(), b.symlink_metadata()) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (a, b);
+        false
+    }
+}
+
+/// Clones the source file to the destination with `FICLONE` so both share their blocks until one
+/// of them is written to, falling back to a regular copy when the filesystem doesn't support it.
+pub fn reflink_or_copy(source: &Path, destination: &Path) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::fd::AsRawFd;
+
+        let source_file = std::fs::File::open(source)?;
+        let destination_file = std::fs::File::create(destination)?;
+
+        // SAFETY: both descriptors are open for the duration of the call.
+        let result = unsafe {
+            libc::ioctl(
+                destination_file.as_raw_fd(),
+                libc::FICLONE,
+                source_file.as_raw_fd(),
+            )
+        };
+
+        if result == 0 {
+            return destination_file.set_permissions(source_file.metadata()?.permissions());
+        }
+    }
+
+    std::fs::copy(source, destination).map(|_| ())
+}
+
+/// Parses a `NAME=VALUE` pair.
+pub fn parse_var(var: &str) -> std::result::Result<(String, String), String> {
+    match var.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("Expected NAME=VALUE: {}", var)),
+    }
+}
+
+/// Parses an octal file mode such as `755`, `0700` or `0o644`.
+pub fn parse_mode(mode: &str) -> std::result::Result<u32, String> {
+    let digits = mode.strip_prefix("0o").unwrap_or(mode);
+
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(format!("Invalid octal mode: {}", mode)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -102,4 +199,62 @@ mod tests {
         let expected = binding.to_str().unwrap();
         assert_eq!(full_path, expected);
     }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("755"), Ok(0o755));
+        assert_eq!(parse_mode("0700"), Ok(0o700));
+        assert_eq!(parse_mode("0o644"), Ok(0o644));
+        assert!(parse_mode("abc").is_err());
+        assert!(parse_mode("77777").is_err());
+    }
+
+    #[test]
+    fn test_get_path_in_root() {
+        let root = Path::new("/image");
+
+        let home = dirs::home_dir().unwrap();
+        let expected = root.join(home.strip_prefix("/").unwrap()).join(".bashrc");
+        assert_eq!(get_path_in_root("~/.bashrc", root).unwrap(), expected);
+
+        assert_eq!(
+            get_path_in_root("/etc/hosts", root).unwrap(),
+            Path::new("/image/etc/hosts")
+        );
+
+        assert_eq!(
+            get_path_in_root("/../etc/./hosts", root).unwrap(),
+            Path::new("/image/etc/hosts")
+        );
+
+        let current_dir = std::env::current_dir().unwrap();
+        assert_eq!(
+            get_path_in_root("./dotfiles", root).unwrap(),
+            root.join(current_dir.strip_prefix("/").unwrap())
+                .join("dotfiles")
+        );
+
+        assert_eq!(
+            get_path_in_root("./dotfiles", &current_dir).unwrap(),
+            current_dir.join("dotfiles")
+        );
+
+        assert_eq!(
+            get_path_in_root("./a/../../x", &current_dir).unwrap(),
+            current_dir
+                .join(current_dir.parent().unwrap().strip_prefix("/").unwrap())
+                .join("x")
+        );
+    }
+
+    #[test]
+    fn test_strip_root() {
+        let root = Path::new("/image");
+
+        assert_eq!(
+            strip_root(Path::new("/image/home/user/.bashrc"), root),
+            Path::new("/home/user/.bashrc")
+        );
+        assert_eq!(strip_root(Path::new("/other"), root), Path::new("/other"));
+    }
 }